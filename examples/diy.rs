@@ -15,7 +15,7 @@ fn main() {
 			ssl: false,
 			host: "example.com",
 			port: 80,
-			method: consts::GET,
+			method: consts::GET.as_str(),
 			path: "/",
 			http_version: "1.1",
 			headers: &headers,
@@ -11,6 +11,7 @@ fn main() {
 		let mut response = minttp::request(&req).unwrap();
 		println!("Status: {} ({})", response.status, response.description);
 		response.body.read_to_string(&mut output).unwrap();
+		println!("Timings: {:?}", response.timings());
 	}
 
 	println!("-------------- High-level flexible request");
@@ -0,0 +1,12 @@
+extern crate minttp;
+use minttp::Request;
+use minttp::har::Recorder;
+
+fn main() {
+	let mut recorder = Recorder::new();
+	recorder.request(&Request::new("example.com".parse().unwrap())).unwrap();
+	recorder.request(&Request::new("example.com/missing".parse().unwrap())).unwrap();
+
+	recorder.save("example.har").unwrap();
+	println!("Saved {} entries to example.har", recorder.entries.len());
+}
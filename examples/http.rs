@@ -4,24 +4,26 @@ extern crate http;
 
 #[cfg(feature = "http")]
 use http::{Request, Uri};
+#[cfg(feature = "http")]
+use minttp::bridge::ReasonPhrase;
 
 #[cfg(feature = "http")]
 fn main() {
 	let uri: Uri = "example.com".parse().unwrap();
-	let mut req = Request::builder()
+	let req = Request::builder()
 		.uri(uri)
-		.method(http::method::GET)
+		.method(http::Method::GET)
 		.header("Test", "Hello World")
-		.body([])
+		.body(Vec::new())
 		.unwrap();
 
 	let output;
 	{
-		let response = minttp::request(&mut req).unwrap().try_into().unwrap();
+		let response = minttp::bridge::request(&req).unwrap().try_into().unwrap();
 		println!(
-			"Status: {}",
-			response.status(),
-			// response.extensions().get::<String>().unwrap()
+			"Status: {} {}",
+			response.status().as_u16(),
+			response.extensions().get::<ReasonPhrase>().unwrap().0
 		);
 		output = String::from_utf8_lossy(response.body()).to_string();
 	}
@@ -0,0 +1,21 @@
+extern crate minttp;
+use minttp::server;
+use std::io::Write;
+use std::net::TcpListener;
+
+fn main() {
+	let listener = TcpListener::bind("127.0.0.1:8080").unwrap();
+	println!("Listening on http://127.0.0.1:8080");
+
+	server::serve_pool(listener, 4, |req, res| {
+		match &*req.path {
+			"/health" => {
+				res.header("Content-Type", "text/plain");
+				res.write_all(b"OK").unwrap();
+			},
+			_ => {
+				res.status(404, "Not Found");
+			}
+		}
+	}).unwrap();
+}
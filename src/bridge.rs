@@ -0,0 +1,405 @@
+use error::Error;
+use http;
+use response::{split_values, Body, Parser, Response};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{BufReader, Read};
+#[cfg(feature = "http-body")]
+use std::io::{self, BufRead};
+#[cfg(feature = "http-body")]
+use std::pin::Pin;
+#[cfg(feature = "http-body")]
+use std::task::{Context, Poll};
+use tls::TlsConfig;
+use url::Url;
+use {diy_request_tls, is_framing_header, speaks_http1, DIYRequest, HttpStream, Request, Timings};
+
+/// The reason phrase of a response, like "Not Found". Stored in the
+/// extensions of converted responses, since `http::Response` has no place
+/// for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReasonPhrase(pub String);
+
+/// The `http_version` of a `DIYRequest` for `version`. minttp only speaks
+/// HTTP/1.x, so anything else is `Error::UnsupportedVersion`.
+pub fn version_str(version: http::Version) -> Result<&'static str, Error> {
+    match version {
+        http::Version::HTTP_10 => Ok("1.0"),
+        http::Version::HTTP_11 => Ok("1.1"),
+        _ => Err(Error::UnsupportedVersion)
+    }
+}
+/// Parse a response's version, like "HTTP/1.1"
+pub fn parse_version(version: &str) -> Result<http::Version, Error> {
+    match version {
+        "HTTP/0.9" => Ok(http::Version::HTTP_09),
+        "HTTP/1.0" => Ok(http::Version::HTTP_10),
+        "HTTP/1.1" => Ok(http::Version::HTTP_11),
+        "HTTP/2" | "HTTP/2.0" => Ok(http::Version::HTTP_2),
+        _ => Err(Error::UnsupportedVersion)
+    }
+}
+
+/// Whether `method` is sent with a body even if it's empty
+fn expects_body(method: &http::Method) -> bool {
+    match *method {
+        http::Method::POST | http::Method::PUT | http::Method::PATCH => true,
+        _ => false
+    }
+}
+
+/// Build the [`DIYRequest`](../struct.DIYRequest.html) that
+/// [`request`](../fn.request.html) would send for `req` and pass it to `f`.
+/// An empty body is only sent for methods that expect one, like POST.
+pub(crate) fn with_diy_request<B, T, F>(req: &http::Request<B>, f: F) -> Result<T, Error>
+    where B: AsRef<[u8]>,
+          F: FnOnce(&DIYRequest) -> Result<T, Error>
+{
+    let uri = req.uri();
+    let ssl = uri.scheme_str() == Some("https");
+    let default_port = if ssl { 443 } else { 80 };
+    let host = uri.host().unwrap_or_default();
+    let port = uri.port_u16().unwrap_or(default_port);
+
+    // Repeated headers are joined, since a DIYRequest has one value per name
+    let mut values: Vec<(&str, Vec<u8>)> = Vec::new();
+    for (name, value) in req.headers().iter().filter(|&(name, _)| !is_framing_header(name.as_str())) {
+        match values.iter_mut().find(|&&mut (existing, _)| existing == name.as_str()) {
+            Some(&mut (_, ref mut existing)) => {
+                existing.extend_from_slice(b", ");
+                existing.extend_from_slice(value.as_bytes());
+            },
+            None => values.push((name.as_str(), value.as_bytes().to_vec()))
+        }
+    }
+    let mut headers: HashMap<&str, &[u8]> = values.iter().map(|&(name, ref value)| (name, &**value)).collect();
+
+    let host_header = {
+        let host = if host.contains(':') && !host.starts_with('[') { format!("[{}]", host) } else { host.to_string() };
+        if port == default_port { host } else { format!("{}:{}", host, port) }
+    };
+    if !headers.contains_key("host") {
+        headers.insert("Host", host_header.as_bytes());
+    }
+    headers.insert("Connection", b"close");
+
+    let body = req.body().as_ref();
+    let body = if body.is_empty() && !expects_body(req.method()) { None } else { Some(body) };
+    let length = body.map(|body| body.len().to_string());
+    if let Some(ref length) = length {
+        headers.insert("Content-Length", length.as_bytes());
+    }
+
+    let path = match uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/") {
+        pq if pq.starts_with('/') || pq == "*" => pq.to_string(),
+        pq => format!("/{}", pq)
+    };
+
+    f(&DIYRequest {
+        ssl: ssl,
+        host: host.trim_start_matches('[').trim_end_matches(']'),
+        port: port,
+        method: req.method().as_str(),
+        path: &path,
+        http_version: version_str(req.version())?,
+        headers: &headers,
+        body: body
+    })
+}
+
+/// Like [`request`](../fn.request.html), but for an `http::Request`. TLS
+/// options can be set with a [`TlsConfig`](../tls/struct.TlsConfig.html)
+/// extension.
+pub fn request<B: AsRef<[u8]>>(req: &http::Request<B>) -> Result<Response<HttpStream>, Error> {
+    let mut timings = Timings::default();
+    let mut response = with_diy_request(req, |diy| {
+        let tls = req.extensions().get::<TlsConfig>().cloned().unwrap_or_default();
+        let stream = diy_request_tls(diy, &tls, &mut timings)?;
+        if !speaks_http1(&stream)? {
+            return Err(Error::UnsupportedProtocol);
+        }
+        Parser::default().parse_for(diy.method, BufReader::new(stream))
+    })?;
+    timings.wait = response.timings.wait;
+    response.timings = timings;
+    Ok(response)
+}
+
+impl TryFrom<Url> for http::Uri {
+    type Error = Error;
+
+    fn try_from(url: Url) -> Result<Self, Error> {
+        format!("{}://{}{}", url.protocol, url.host_header(), url.fullpath)
+            .parse()
+            .map_err(|_| Error::InvalidUrl)
+    }
+}
+impl<'a> TryFrom<&'a http::Uri> for Url {
+    type Error = Error;
+
+    /// Fails if `uri` isn't absolute, like "/path"
+    fn try_from(uri: &'a http::Uri) -> Result<Self, Error> {
+        if uri.scheme_str().is_none() || uri.host().is_none() {
+            return Err(Error::InvalidUrl);
+        }
+        uri.to_string().parse().map_err(|_| Error::InvalidUrl)
+    }
+}
+impl TryFrom<http::Uri> for Url {
+    type Error = Error;
+
+    fn try_from(uri: http::Uri) -> Result<Self, Error> { Url::try_from(&uri) }
+}
+
+impl TryFrom<Request> for http::Request<Vec<u8>> {
+    type Error = Error;
+
+    /// Only the URL, method, headers, body and TLS options are kept
+    fn try_from(req: Request) -> Result<Self, Error> {
+        let mut builder = http::Request::builder()
+            .method(req.method.as_str())
+            .uri(http::Uri::try_from(req.url)?)
+            .extension(req.tls);
+        for (name, value) in &req.headers {
+            builder = builder.header(&**name, &**value);
+        }
+        builder.body(req.body.unwrap_or_default()).map_err(|_| Error::InvalidRequest)
+    }
+}
+impl<B: Into<Vec<u8>>> TryFrom<http::Request<B>> for Request {
+    type Error = Error;
+
+    /// Fails unless the version is HTTP/1.1, the only one `Request` sends.
+    /// Repeated headers are joined with ", ".
+    fn try_from(req: http::Request<B>) -> Result<Self, Error> {
+        let (parts, body) = req.into_parts();
+        if parts.version != http::Version::HTTP_11 {
+            return Err(Error::UnsupportedVersion);
+        }
+
+        let mut request = Request::new(Url::try_from(&parts.uri)?).method(parts.method.as_str().parse()?);
+        for (name, value) in &parts.headers {
+            request.headers.entry(name.as_str().to_string())
+                .and_modify(|existing| existing.extend_from_slice(b", "))
+                .or_default()
+                .extend_from_slice(value.as_bytes());
+        }
+        if let Some(tls) = parts.extensions.get::<TlsConfig>() {
+            request.tls = tls.clone();
+        }
+        let body = body.into();
+        if !body.is_empty() || expects_body(&parts.method) {
+            request.body = Some(body);
+        }
+        Ok(request)
+    }
+}
+
+/// Like [`Response::is_reusable`](../response/struct.Response.html#method.is_reusable),
+/// for a response converted with
+/// [`into_http`](../response/struct.Response.html#method.into_http).
+/// Once this is true, `res.into_body().into_inner()` is ready for another
+/// request.
+pub fn is_reusable<Stream: Read>(res: &http::Response<Body<Stream>>) -> bool {
+    let keep_alive = match res.headers().get(http::header::CONNECTION).map(|val| val.as_bytes()) {
+        Some(val) if val.eq_ignore_ascii_case(b"close") => false,
+        Some(val) if val.eq_ignore_ascii_case(b"keep-alive") => true,
+        _ => res.version() == http::Version::HTTP_11
+    };
+    keep_alive && res.body().framed_ref().is_reusable()
+}
+
+impl<Stream: Read> Response<Stream> {
+    /// Convert to an `http::Response` without reading the body, which keeps
+    /// its framing and any decoding set up by
+    /// [`decompress`](#method.decompress). The reason phrase and timings are
+    /// stored as [`ReasonPhrase`](../bridge/struct.ReasonPhrase.html) and
+    /// [`Timings`](../timing/struct.Timings.html) extensions.
+    pub fn into_http(self) -> Result<http::Response<Body<Stream>>, Error> {
+        let mut response = http::Response::new(self.body);
+        *response.version_mut() = parse_version(&self.http_version)?;
+        *response.status_mut() = http::StatusCode::from_u16(self.status.as_u16())
+            .map_err(|_| Error::InvalidStatusCode)?;
+        for (name, value) in &self.headers {
+            let name = http::header::HeaderName::from_bytes(name.as_bytes()).map_err(|_| Error::InvalidHeader)?;
+            for value in split_values(value) {
+                let value = http::header::HeaderValue::from_bytes(value).map_err(|_| Error::InvalidHeader)?;
+                response.headers_mut().append(name.clone(), value);
+            }
+        }
+        response.extensions_mut().insert(ReasonPhrase(self.description));
+        response.extensions_mut().insert(self.timings);
+        Ok(response)
+    }
+    /// Like [`into_http`](#method.into_http), but reads the whole body
+    pub fn try_into(self) -> Result<http::Response<Vec<u8>>, Error> {
+        let (parts, mut body) = self.into_http()?.into_parts();
+        let mut buf = Vec::new();
+        body.read_to_end(&mut buf)?;
+        Ok(http::Response::from_parts(parts, buf))
+    }
+}
+
+#[cfg(feature = "http-body")]
+/// Each frame is whatever was buffered from the connection. Reading blocks,
+/// so polling never returns `Poll::Pending`: poll it where blocking is fine,
+/// like `tokio::task::spawn_blocking`. Trailers are skipped.
+impl<Stream: Read + Unpin> http_body::Body for Body<Stream> {
+    type Data = bytes::Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Option<io::Result<http_body::Frame<bytes::Bytes>>>> {
+        let body = self.get_mut();
+        let data = match body.fill_buf() {
+            Ok(&[]) => return Poll::Ready(None),
+            Ok(buf) => bytes::Bytes::copy_from_slice(buf),
+            Err(err) => return Poll::Ready(Some(Err(err)))
+        };
+        body.consume(data.len());
+        Poll::Ready(Some(Ok(http_body::Frame::data(data))))
+    }
+    fn is_end_stream(&self) -> bool {
+        !self.is_decoded() && self.framed_ref().is_done()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use server;
+    use std::net::TcpListener;
+    use std::io::{BufReader, Cursor};
+    use std::thread;
+    use write_request;
+    use Method;
+
+    fn serialize(req: &http::Request<Vec<u8>>) -> String {
+        let mut out = Vec::new();
+        with_diy_request(req, |diy| write_request(&mut out, diy)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn serialization() {
+        let req = http::Request::get("http://example.com:8080/search?q=a%20b").body(Vec::new()).unwrap();
+        assert_eq!(serialize(&req), "GET /search?q=a%20b HTTP/1.1\r\nHost:example.com:8080\r\nConnection:close\r\n\r\n");
+
+        let req = http::Request::post("http://[::1]/").header("X-A", "1").header("x-a", "2").body(Vec::new()).unwrap();
+        assert_eq!(serialize(&req), "POST / HTTP/1.1\r\nHost:[::1]\r\nConnection:close\r\nContent-Length:0\r\nx-a:1, 2\r\n\r\n");
+
+        let req = http::Request::put("http://example.com").version(http::Version::HTTP_10).body(b"hi".to_vec()).unwrap();
+        assert_eq!(serialize(&req), "PUT / HTTP/1.0\r\nHost:example.com\r\nConnection:close\r\nContent-Length:2\r\n\r\nhi");
+
+        let req = http::Request::get("http://example.com").version(http::Version::HTTP_2).body(Vec::new()).unwrap();
+        match with_diy_request(&req, |_| Ok(())) { Err(Error::UnsupportedVersion) => (), _ => panic!() }
+
+        let req = http::Request::post("http://example.com")
+            .header("content-length", "3")
+            .header("connection", "keep-alive")
+            .body(b"hello".to_vec())
+            .unwrap();
+        assert_eq!(serialize(&req), "POST / HTTP/1.1\r\nHost:example.com\r\nConnection:close\r\nContent-Length:5\r\n\r\nhello");
+    }
+
+    #[test]
+    fn round_trip() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || server::serve(listener, |req, res| {
+            res.status(418, "I'm a teapot").header("X-Path", req.path.clone());
+            res.body.extend_from_slice(&req.body);
+        }));
+
+        let uri = format!("http://127.0.0.1:{}/brew?tea=green", port);
+        let req = http::Request::post(&*uri).body(b"leaves".to_vec()).unwrap();
+        let res = request(&req).unwrap().try_into().unwrap();
+
+        assert_eq!(res.status(), http::StatusCode::IM_A_TEAPOT);
+        assert_eq!(res.version(), http::Version::HTTP_11);
+        assert_eq!(res.extensions().get::<ReasonPhrase>(), Some(&ReasonPhrase("I'm a teapot".to_string())));
+        assert_eq!(res.headers()["x-path"], "/brew?tea=green");
+        assert_eq!(res.body(), b"leaves");
+    }
+
+    #[test]
+    fn conversions() {
+        let url: Url = "http://[::1]:8080/a?b=c".parse().unwrap();
+        let uri = http::Uri::try_from(url.clone()).unwrap();
+        assert_eq!(uri, "http://[::1]:8080/a?b=c");
+        assert_eq!(Url::try_from(uri).unwrap(), url);
+        assert_eq!(http::Uri::try_from("https://example.com".parse::<Url>().unwrap()).unwrap(), "https://example.com/");
+        match Url::try_from(http::Uri::from_static("/relative")) { Err(Error::InvalidUrl) => (), _ => panic!() }
+
+        let req = Request::new("http://example.com/x".parse().unwrap())
+            .method(Method::Put)
+            .header("X-A", "1")
+            .body(b"hi".to_vec());
+        let converted = http::Request::try_from(req.clone()).unwrap();
+        assert_eq!((converted.method(), converted.uri().path()), (&http::Method::PUT, "/x"));
+        assert_eq!(converted.headers()["x-a"], "1");
+        assert_eq!(converted.body(), b"hi");
+        let back = Request::try_from(converted).unwrap();
+        assert_eq!((&back.url, &back.method, &back.body), (&req.url, &req.method, &req.body));
+        assert_eq!(back.headers.get("x-a").map(|v| &**v), Some(&b"1"[..]));
+
+        let req = http::Request::get("http://example.com").header("A", "1").header("a", "2").body(Vec::new()).unwrap();
+        let req = Request::try_from(req).unwrap();
+        assert_eq!((&req.headers["a"][..], req.body), (&b"1, 2"[..], None));
+        let req = http::Request::get("http://example.com").version(http::Version::HTTP_10).body(Vec::new()).unwrap();
+        match Request::try_from(req) { Err(Error::UnsupportedVersion) => (), _ => panic!() }
+
+        let raw = b"HTTP/1.1 200 OK\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2\r\nContent-Length: 0\r\n\r\n".to_vec();
+        let res = Response::new(BufReader::new(Cursor::new(raw))).unwrap().into_http().unwrap();
+        let cookies: Vec<_> = res.headers().get_all("set-cookie").iter().collect();
+        assert_eq!(cookies, vec!["a=1", "b=2"]);
+    }
+
+    #[test]
+    fn streaming() {
+        let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nContent-Encoding: gzip\r\n\r\n";
+        let gzipped = ::encoding::gzip(b"hello world");
+        let mut stream = raw.to_vec();
+        stream.extend_from_slice(format!("{:x}\r\n", gzipped.len()).as_bytes());
+        stream.extend_from_slice(&gzipped);
+        stream.extend_from_slice(b"\r\n0\r\n\r\nHTTP/1.1 204 No Content\r\n\r\n");
+
+        let res = Response::new(BufReader::new(Cursor::new(stream))).unwrap().decompress(1024);
+        let mut res = res.into_http().unwrap();
+        assert!(res.headers().get("content-encoding").is_none());
+        assert!(res.extensions().get::<Timings>().is_some());
+        assert!(!is_reusable(&res));
+
+        let mut body = String::new();
+        res.body_mut().read_to_string(&mut body).unwrap();
+        assert_eq!(body, "hello world");
+        assert!(is_reusable(&res));
+
+        let next = Response::new(res.into_body().into_inner()).unwrap();
+        assert_eq!(next.status, 204);
+    }
+
+    #[cfg(feature = "http-body")]
+    #[test]
+    fn http_body() {
+        use http_body::Body as HttpBody;
+        use std::pin::Pin;
+        use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+        fn noop(_: *const ()) {}
+        fn clone(_: *const ()) -> RawWaker { RawWaker::new(::std::ptr::null(), &VTABLE) }
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        let waker = unsafe { Waker::from_raw(clone(::std::ptr::null())) };
+        let mut cx = Context::from_waker(&waker);
+
+        let stream = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhellonext".to_vec();
+        let mut body = Response::new(BufReader::new(Cursor::new(stream))).unwrap().into_http().unwrap().into_body();
+        assert!(!body.is_end_stream());
+
+        let mut data = Vec::new();
+        while let Poll::Ready(Some(frame)) = Pin::new(&mut body).poll_frame(&mut cx) {
+            data.extend_from_slice(&frame.unwrap().into_data().unwrap());
+        }
+        assert_eq!(data, b"hello");
+        assert!(body.is_end_stream());
+    }
+}
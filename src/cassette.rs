@@ -0,0 +1,281 @@
+use error::Error;
+use response::Response;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use timing::Timings;
+use tls;
+use {finish_response, open, with_diy_request, write_request, HttpStream, Request};
+
+/// One recorded request and the response it got
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exchange {
+    pub url: String,
+    /// The serialized request: request line, headers and body
+    pub request: Vec<u8>,
+    /// The raw response: status line, headers and body
+    pub response: Vec<u8>
+}
+impl Exchange {
+    /// The method, taken from the request line
+    pub fn method(&self) -> &[u8] {
+        self.request.split(|&b| b == b' ').next().unwrap_or_default()
+    }
+    /// The request body, i.e. everything after the headers
+    pub fn body(&self) -> &[u8] {
+        match self.request.windows(4).position(|w| w == b"\r\n\r\n") {
+            Some(i) => &self.request[i + 4..],
+            None => &[]
+        }
+    }
+}
+
+/// What a cassette does with requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Always hit the network and record every exchange, replacing whatever
+    /// the cassette contained before.
+    Record,
+    /// Serve recorded responses. Unknown requests are performed and
+    /// recorded, unless the cassette is strict.
+    Replay
+}
+
+/// Which parts of a request have to be equal for a recorded response to be
+/// served. Defaults to method and URL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Matcher {
+    pub method: bool,
+    pub url: bool,
+    pub body: bool
+}
+impl Default for Matcher {
+    fn default() -> Self {
+        Matcher {
+            method: true,
+            url: true,
+            body: false
+        }
+    }
+}
+
+/// A file of recorded exchanges, which can be used instead of the network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cassette {
+    pub path: PathBuf,
+    pub mode: Mode,
+    pub matcher: Matcher,
+    pub strict: bool,
+    pub exchanges: Vec<Exchange>,
+    used: Vec<bool>
+}
+impl Cassette {
+    /// Create an empty cassette which records to `path`
+    pub fn record<P: Into<PathBuf>>(path: P) -> Cassette {
+        Cassette {
+            path: path.into(),
+            mode: Mode::Record,
+            matcher: Matcher::default(),
+            strict: false,
+            exchanges: Vec::new(),
+            used: Vec::new()
+        }
+    }
+    /// Load a cassette from `path` for replaying
+    pub fn replay<P: Into<PathBuf>>(path: P) -> Result<Cassette, Error> {
+        let path = path.into();
+        let exchanges = load(&path)?;
+        Ok(Cassette {
+            used: vec![false; exchanges.len()],
+            path: path,
+            mode: Mode::Replay,
+            matcher: Matcher::default(),
+            strict: false,
+            exchanges: exchanges
+        })
+    }
+
+    /// Set which parts of a request to match on
+    pub fn matcher(mut self, matcher: Matcher) -> Self {
+        self.matcher = matcher;
+        self
+    }
+    /// If strict, replaying a request that isn't in the cassette returns
+    /// `Error::UnmatchedRequest` instead of touching the network.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Like [`request`](../fn.request.html), but recorded or replayed
+    /// depending on the mode.
+    pub fn request(&mut self, req: &Request) -> Result<Response<HttpStream>, Error> {
+        let url = req.url.to_string();
+        let mut raw = Vec::new();
+        with_diy_request(req, |diy| write_request(&mut raw, diy))?;
+
+        if self.mode == Mode::Replay {
+            if let Some(i) = self.find(&url, &raw) {
+                self.used[i] = true;
+                return respond(req, self.exchanges[i].response.clone());
+            }
+            if self.strict {
+                return Err(Error::UnmatchedRequest);
+            }
+        }
+
+        // Connected like any other request, so the TLS settings and pins
+        // apply, but the response is kept as it was sent
+        let mut response = Vec::new();
+        with_diy_request(req, |diy| open(req, diy, &tls::SHARED, &mut Timings::default()))?.read_to_end(&mut response)?;
+
+        self.exchanges.push(Exchange {
+            url: url,
+            request: raw,
+            response: response.clone()
+        });
+        self.used.push(true);
+        self.save()?;
+
+        respond(req, response)
+    }
+
+    fn find(&self, url: &str, raw: &[u8]) -> Option<usize> {
+        let wanted = Exchange {
+            url: url.to_string(),
+            request: raw.to_vec(),
+            response: Vec::new()
+        };
+        let matches = |e: &Exchange| {
+            (!self.matcher.method || e.method() == wanted.method())
+                && (!self.matcher.url || e.url == wanted.url)
+                && (!self.matcher.body || e.body() == wanted.body())
+        };
+
+        // Prefer exchanges that haven't been replayed yet, so repeated
+        // requests get their responses in the recorded order.
+        self.exchanges.iter().enumerate()
+            .position(|(i, e)| !self.used[i] && matches(e))
+            .or_else(|| self.exchanges.iter().position(|e| matches(e)))
+    }
+
+    /// Write all exchanges to the cassette file
+    pub fn save(&self) -> io::Result<()> {
+        let mut file = File::create(&self.path)?;
+        for exchange in &self.exchanges {
+            write!(file, "url {}\n", exchange.url)?;
+            write!(file, "request {}\n", exchange.request.len())?;
+            file.write_all(&exchange.request)?;
+            write!(file, "\nresponse {}\n", exchange.response.len())?;
+            file.write_all(&exchange.response)?;
+            file.write_all(b"\n")?;
+        }
+        file.flush()
+    }
+}
+
+fn respond(req: &Request, response: Vec<u8>) -> Result<Response<HttpStream>, Error> {
+    let response = req.parser.parse_for(req.method.as_str(), BufReader::new(HttpStream::Memory(Cursor::new(response))))?;
+    Ok(finish_response(req, response))
+}
+
+fn load(path: &Path) -> Result<Vec<Exchange>, Error> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut exchanges = Vec::new();
+
+    loop {
+        let url = match field(&mut file, "url")? {
+            Some(url) => url,
+            None => break
+        };
+        let request = block(&mut file, "request")?;
+        let response = block(&mut file, "response")?;
+        exchanges.push(Exchange {
+            url: url,
+            request: request,
+            response: response
+        });
+    }
+
+    Ok(exchanges)
+}
+fn field<R: BufRead>(file: &mut R, name: &str) -> Result<Option<String>, Error> {
+    let mut line = String::new();
+    if file.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.trim_end_matches('\n').splitn(2, ' ');
+    if parts.next() != Some(name) {
+        return Err(Error::InvalidCassette);
+    }
+    match parts.next() {
+        Some(value) => Ok(Some(value.to_string())),
+        None => Err(Error::InvalidCassette)
+    }
+}
+fn block<R: BufRead>(file: &mut R, name: &str) -> Result<Vec<u8>, Error> {
+    let len: usize = match field(file, name)? {
+        Some(len) => len.parse()?,
+        None => return Err(Error::InvalidCassette)
+    };
+    let mut data = vec![0; len + 1];
+    file.read_exact(&mut data)?;
+    if data.pop() != Some(b'\n') {
+        return Err(Error::InvalidCassette);
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use server;
+    use std::env;
+    use std::net::TcpListener;
+    use std::thread;
+    #[cfg(feature = "rustls")]
+    use tls::{Backend, TlsConfig};
+
+    #[test]
+    fn record_and_replay() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || server::serve(listener, |req, res| {
+            res.write_all(req.path.as_bytes()).unwrap();
+        }));
+
+        let path = env::temp_dir().join(format!("minttp-cassette-{}", port));
+        let req = Request::new(format!("127.0.0.1:{}/hello", port).parse().unwrap());
+
+        let mut body = String::new();
+        let mut cassette = Cassette::record(&path);
+        cassette.request(&req).unwrap().body.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "/hello");
+
+        let mut cassette = Cassette::replay(&path).unwrap().strict(true);
+        assert_eq!(cassette.exchanges.len(), 1);
+        assert_eq!(cassette.exchanges[0].method(), b"GET");
+
+        body.clear();
+        cassette.request(&req).unwrap().body.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "/hello");
+
+        let other = req.clone().url(format!("127.0.0.1:{}/other", port).parse().unwrap());
+        match cassette.request(&other) {
+            Err(Error::UnmatchedRequest) => (),
+            _ => panic!("expected an unmatched request")
+        }
+        let _ = ::std::fs::remove_file(&path);
+    }
+    #[cfg(feature = "rustls")]
+    #[test]
+    fn tls() {
+        let addr = tls::test::server(1, &[]);
+        let path = env::temp_dir().join(format!("minttp-cassette-{}", addr.port()));
+        let config = TlsConfig::new().backend(Backend::Rustls).danger_accept_invalid_certs(true).pin_sha256([0; 32]);
+        let req = Request::new(format!("https://localhost:{}/", addr.port()).parse().unwrap()).tls(config);
+
+        match Cassette::record(&path).request(&req) { Err(Error::PinMismatch) => (), _ => panic!() }
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,186 @@
+use error::Error;
+use response::Response;
+use retry::RetryPolicy;
+use std::thread;
+use std::time::{Duration, Instant};
+use tls::{Connectors, TlsConfig};
+use {request_cached, HttpStream, Request};
+
+/// Hooks that run around every request made by a [`Client`](struct.Client.html).
+pub trait Interceptor: Send + Sync {
+    /// Inspect or modify a request before it's serialized
+    fn before(&self, _req: &mut Request) {}
+    /// Inspect the outcome of a request. `elapsed` is the time until the
+    /// response headers were parsed.
+    fn after(&self, _req: &Request, _res: Result<&Response<HttpStream>, &Error>, _elapsed: Duration) {}
+}
+impl<F> Interceptor for F
+    where F: Fn(&mut Request) + Send + Sync
+{
+    fn before(&self, req: &mut Request) { self(req) }
+}
+
+/// A reusable request configuration, which runs every request through its
+/// interceptors in the order they were added.
+#[derive(Default)]
+pub struct Client {
+    interceptors: Vec<Box<Interceptor>>,
+    retry: Option<RetryPolicy>,
+    tls: Option<TlsConfig>,
+    connectors: Connectors
+}
+impl Client {
+    /// Create a client without any interceptors
+    pub fn new() -> Client { Client::default() }
+
+    /// Add an interceptor to the end of the chain
+    pub fn interceptor<I: Interceptor + 'static>(mut self, interceptor: I) -> Self {
+        self.interceptors.push(Box::new(interceptor));
+        self
+    }
+    /// Retry failed requests according to `policy`
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+    /// Use `tls` for every request, instead of the request's own TLS options
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Run the interceptors and perform the request, retrying if there's a
+    /// retry policy. The interceptors see every attempt.
+    /// See [`request`](../fn.request.html)
+    pub fn request(&self, req: &Request) -> Result<Response<HttpStream>, Error> {
+        let mut req = req.clone();
+        if let Some(ref tls) = self.tls {
+            req.tls = tls.clone();
+        }
+        for interceptor in &self.interceptors {
+            interceptor.before(&mut req);
+        }
+
+        let mut attempt = 1;
+        loop {
+            let start = Instant::now();
+            let result = request_cached(&req, &self.connectors);
+            let elapsed = start.elapsed();
+
+            for interceptor in &self.interceptors {
+                interceptor.after(&req, result.as_ref(), elapsed);
+            }
+
+            let delay = self.retry.as_ref().and_then(|policy| policy.should_retry(&req.method, attempt, result.as_ref()));
+            match delay {
+                Some(delay) => thread::sleep(delay),
+                None => return result
+            }
+            attempt += 1;
+        }
+    }
+}
+
+/// Headers whose values are never logged
+pub const REDACTED_HEADERS: &[&str] = &["Authorization", "Proxy-Authorization", "Cookie", "Set-Cookie"];
+
+/// An interceptor that logs every request with its method, URL, status and
+/// duration. Sensitive headers are redacted. See
+/// [`REDACTED_HEADERS`](constant.REDACTED_HEADERS.html)
+pub struct Logger {
+    headers: bool,
+    sink: Box<Fn(&str) + Send + Sync>
+}
+impl Default for Logger {
+    fn default() -> Self { Logger::new() }
+}
+impl Logger {
+    /// Create a logger which prints to stderr
+    pub fn new() -> Logger {
+        Logger::with(|line| eprintln!("{}", line))
+    }
+    /// Create a logger which passes each line to `sink`
+    pub fn with<F: Fn(&str) + Send + Sync + 'static>(sink: F) -> Logger {
+        Logger {
+            headers: false,
+            sink: Box::new(sink)
+        }
+    }
+    /// Also log request and response headers
+    pub fn headers(mut self, headers: bool) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    fn log_headers<'a, I>(&self, prefix: char, headers: I)
+        where I: Iterator<Item = (&'a String, &'a Vec<u8>)>
+    {
+        for (name, value) in headers {
+            if REDACTED_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(name)) {
+                (self.sink)(&format!("{} {}: [redacted]", prefix, name));
+            } else {
+                (self.sink)(&format!("{} {}: {}", prefix, name, String::from_utf8_lossy(value)));
+            }
+        }
+    }
+}
+impl Interceptor for Logger {
+    fn after(&self, req: &Request, res: Result<&Response<HttpStream>, &Error>, elapsed: Duration) {
+        let millis = elapsed.as_secs() * 1000 + elapsed.subsec_nanos() as u64 / 1_000_000;
+        match res {
+            Ok(res) => (self.sink)(&format!(
+                "{} {} -> {} {} ({}ms)",
+                req.method,
+                req.url,
+                res.status,
+                res.description,
+                millis
+            )),
+            Err(err) => (self.sink)(&format!("{} {} -> error: {} ({}ms)", req.method, req.url, err, millis))
+        }
+        if self.headers {
+            self.log_headers('>', req.headers.iter());
+            if let Ok(res) = res {
+                self.log_headers('<', res.headers.iter());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use server;
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    #[test]
+    fn interceptors() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || server::serve(listener, |req, res| {
+            let trace = req.headers.get("X-Trace-Id").cloned().unwrap_or_default();
+            res.header("X-Trace-Id", trace);
+        }));
+
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&lines);
+        let client = Client::new()
+            .interceptor(|req: &mut Request| {
+                req.headers.insert("X-Trace-Id".to_string(), b"abc".to_vec());
+            })
+            .interceptor(Logger::with(move |line| log.lock().unwrap().push(line.to_string())).headers(true));
+
+        let req = Request::new(format!("127.0.0.1:{}/", port).parse().unwrap())
+            .header("Authorization", "Bearer secret");
+        let res = client.request(&req).unwrap();
+        assert_eq!(res.headers.get("X-Trace-Id").unwrap(), b"abc");
+
+        let lines = lines.lock().unwrap();
+        assert!(lines[0].starts_with(&format!("GET http://127.0.0.1:{}/ -> 200 OK (", port)));
+        assert!(lines.contains(&"> Authorization: [redacted]".to_string()));
+        assert!(lines.contains(&"> X-Trace-Id: abc".to_string()));
+        assert!(!lines.iter().any(|line| line.contains("secret")));
+    }
+}
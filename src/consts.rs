@@ -1,9 +1,11 @@
-pub const GET: &str = "GET";
-pub const HEAD: &str = "HEAD";
-pub const POST: &str = "POST";
-pub const PUT: &str = "PUT";
-pub const DELETE: &str = "DELETE";
-pub const CONNECT: &str = "CONNECT";
-pub const OPTIONS: &str = "OPTIONS";
-pub const TRACE: &str = "TRACE";
-pub const PATCH: &str = "PATCH";
+use method::Method;
+
+pub const GET: Method = Method::Get;
+pub const HEAD: Method = Method::Head;
+pub const POST: Method = Method::Post;
+pub const PUT: Method = Method::Put;
+pub const DELETE: Method = Method::Delete;
+pub const CONNECT: Method = Method::Connect;
+pub const OPTIONS: Method = Method::Options;
+pub const TRACE: Method = Method::Trace;
+pub const PATCH: Method = Method::Patch;
@@ -0,0 +1,104 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Format a time as ISO 8601 in UTC, e.g. `2017-08-19T12:00:00.000Z`
+pub fn iso8601(time: SystemTime) -> String {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs / 3600 % 24,
+        secs / 60 % 60,
+        secs % 60,
+        since.subsec_nanos() / 1_000_000
+    )
+}
+/// Convert days since the unix epoch into a (year, month, day) date
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+/// Convert a (year, month, day) date into days since the unix epoch
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Parse an HTTP-date in any of the three formats HTTP allows:
+/// `Sun, 06 Nov 1994 08:49:37 GMT`, `Sunday, 06-Nov-94 08:49:37 GMT` and
+/// `Sun Nov  6 08:49:37 1994`
+pub fn parse_http_date(input: &str) -> Option<SystemTime> {
+    let input = input.replace(|c| c == ',' || c == '-', " ");
+    let mut parts = input.split_whitespace().skip(1);
+    let month = |s: &str| MONTHS.iter().position(|m| *m == s).map(|i| i as u32 + 1);
+
+    let first = parts.next()?;
+    let (day, month, year, time) = match month(first) {
+        Some(month) => {
+            let day = parts.next()?;
+            let time = parts.next()?;
+            (day, month, parts.next()?, time)
+        },
+        None => {
+            let month = month(parts.next()?)?;
+            let year = parts.next()?;
+            (first, month, year, parts.next()?)
+        }
+    };
+
+    let day: u32 = day.parse().ok()?;
+    let mut year: i64 = year.parse().ok()?;
+    if year < 100 {
+        year += if year < 70 { 2000 } else { 1900 };
+    }
+    let mut time = time.split(':').map(|s| s.parse::<u64>());
+    let (hour, min, sec) = match (time.next(), time.next(), time.next()) {
+        (Some(Ok(h)), Some(Ok(m)), Some(Ok(s))) if h < 24 && m < 60 && s <= 60 => (h, m, s),
+        _ => return None
+    };
+    if day == 0 || day > 31 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    if days < 0 {
+        return None;
+    }
+    Some(UNIX_EPOCH + Duration::from_secs(days as u64 * 86400 + hour * 3600 + min * 60 + sec))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dates() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_503_144_000_123);
+        assert_eq!(iso8601(time), "2017-08-19T12:00:00.123Z");
+
+        let time = Some(UNIX_EPOCH + Duration::from_secs(784111777));
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), time);
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), time);
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), time);
+        assert_eq!(parse_http_date("Sun, 06 Foo 1994 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("120"), None);
+    }
+}
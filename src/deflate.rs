@@ -0,0 +1,232 @@
+use std::io::{self, Write};
+
+/// How far back a back-reference may reach
+const WINDOW: usize = 32768;
+/// The longest possible match
+const MAX_MATCH: usize = 258;
+const MIN_MATCH: usize = 3;
+/// How much input to collect before compressing it as one block
+const BLOCK: usize = 65536;
+/// How many earlier positions to try per match. Higher is smaller but slower.
+const MAX_CHAIN: usize = 128;
+const HASH_BITS: usize = 15;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13
+];
+
+/// Writes bits least significant first
+struct Bits {
+    out: Vec<u8>,
+    buf: u32,
+    count: u32
+}
+impl Bits {
+    fn bits(&mut self, value: u32, n: u32) {
+        self.buf |= value << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.out.push(self.buf as u8);
+            self.buf >>= 8;
+            self.count -= 8;
+        }
+    }
+    /// Huffman codes are sent most significant bit first
+    fn code(&mut self, code: u32, len: u32) {
+        let mut reversed = 0;
+        for i in 0..len {
+            reversed |= (code >> i & 1) << (len - 1 - i);
+        }
+        self.bits(reversed, len);
+    }
+    /// Pad the last byte with zeroes
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.out.push(self.buf as u8);
+            self.buf = 0;
+            self.count = 0;
+        }
+    }
+
+    fn literal(&mut self, sym: u16) {
+        match sym {
+            0..=143 => self.code(0x30 + sym as u32, 8),
+            144..=255 => self.code(0x190 + sym as u32 - 144, 9),
+            256..=279 => self.code(sym as u32 - 256, 7),
+            _ => self.code(0xC0 + sym as u32 - 280, 8)
+        }
+    }
+    fn back_reference(&mut self, len: usize, dist: usize) {
+        let i = LENGTH_BASE.iter().rposition(|&base| base as usize <= len).unwrap();
+        self.literal(257 + i as u16);
+        self.bits((len - LENGTH_BASE[i] as usize) as u32, LENGTH_EXTRA[i] as u32);
+
+        let i = DIST_BASE.iter().rposition(|&base| base as usize <= dist).unwrap();
+        self.code(i as u32, 5);
+        self.bits((dist - DIST_BASE[i] as usize) as u32, DIST_EXTRA[i] as u32);
+    }
+}
+
+/// A streaming raw DEFLATE (RFC 1951) compressor. Uses LZ77 with hash chains
+/// and the fixed Huffman codes, which is simple and still does well on text.
+pub struct Deflater<W: Write> {
+    inner: W,
+    bits: Bits,
+    /// Input starting at absolute position `base`: up to `WINDOW` bytes of
+    /// history followed by input that hasn't been compressed yet.
+    buf: Vec<u8>,
+    base: usize,
+    pos: usize,
+    head: Vec<usize>,
+    prev: Vec<usize>
+}
+impl<W: Write> Deflater<W> {
+    /// Compress everything written into `inner`
+    pub fn new(inner: W) -> Deflater<W> {
+        Deflater {
+            inner: inner,
+            bits: Bits {
+                out: Vec::new(),
+                buf: 0,
+                count: 0
+            },
+            buf: Vec::new(),
+            base: 0,
+            pos: 0,
+            head: vec![0; 1 << HASH_BITS],
+            prev: vec![0; WINDOW]
+        }
+    }
+
+    pub fn get_ref(&self) -> &W { &self.inner }
+    pub fn get_mut(&mut self) -> &mut W { &mut self.inner }
+
+    /// Compress the remaining input as the final block and return the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.block(true)?;
+        self.bits.align();
+        self.inner.write_all(&self.bits.out)?;
+        Ok(self.inner)
+    }
+
+    fn hash(&self, pos: usize) -> usize {
+        let i = pos - self.base;
+        let h = (self.buf[i] as usize) << 10 ^ (self.buf[i + 1] as usize) << 5 ^ self.buf[i + 2] as usize;
+        h & ((1 << HASH_BITS) - 1)
+    }
+    /// Remember `pos` as a place where its first three bytes occur. Positions
+    /// are stored plus one, so zero means none.
+    fn insert(&mut self, pos: usize) {
+        if pos + MIN_MATCH > self.base + self.buf.len() {
+            return;
+        }
+        let h = self.hash(pos);
+        self.prev[pos % WINDOW] = self.head[h];
+        self.head[h] = pos + 1;
+    }
+    fn longest_match(&self, pos: usize, end: usize) -> (usize, usize) {
+        let (mut best_len, mut best_dist) = (0, 0);
+        if pos + MIN_MATCH > end {
+            return (best_len, best_dist);
+        }
+        let max = (end - pos).min(MAX_MATCH);
+        let current = &self.buf[pos - self.base..];
+
+        let mut candidate = self.head[self.hash(pos)];
+        for _ in 0..MAX_CHAIN {
+            if candidate == 0 {
+                break;
+            }
+            let cand = candidate - 1;
+            if cand < self.base || cand >= pos || pos - cand > WINDOW {
+                break;
+            }
+            let previous = &self.buf[cand - self.base..];
+            let len = current[..max].iter().zip(previous).take_while(|&(a, b)| a == b).count();
+            if len > best_len {
+                best_len = len;
+                best_dist = pos - cand;
+                if len == max {
+                    break;
+                }
+            }
+            candidate = self.prev[cand % WINDOW];
+        }
+        (best_len, best_dist)
+    }
+
+    /// Compress all pending input as one fixed Huffman block
+    fn block(&mut self, last: bool) -> io::Result<()> {
+        let end = self.base + self.buf.len();
+        if self.pos == end && !last {
+            return Ok(());
+        }
+
+        self.bits.bits(last as u32, 1);
+        self.bits.bits(1, 2);
+        while self.pos < end {
+            let (len, dist) = self.longest_match(self.pos, end);
+            if len >= MIN_MATCH {
+                self.bits.back_reference(len, dist);
+                for pos in self.pos..self.pos + len {
+                    self.insert(pos);
+                }
+                self.pos += len;
+            } else {
+                self.bits.literal(self.buf[self.pos - self.base] as u16);
+                let pos = self.pos;
+                self.insert(pos);
+                self.pos += 1;
+            }
+        }
+        self.bits.literal(256);
+
+        self.inner.write_all(&self.bits.out)?;
+        self.bits.out.clear();
+
+        if self.buf.len() > WINDOW {
+            let drop = self.buf.len() - WINDOW;
+            self.buf.drain(..drop);
+            self.base += drop;
+        }
+        Ok(())
+    }
+}
+impl<W: Write> Write for Deflater<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.base + self.buf.len() - self.pos >= BLOCK {
+            self.block(false)?;
+        }
+        Ok(buf.len())
+    }
+    /// Compress everything written so far and flush it to the inner writer,
+    /// so it can all be decoded before the stream ends. Output stays valid,
+    /// but flushing often hurts compression.
+    fn flush(&mut self) -> io::Result<()> {
+        self.block(false)?;
+        // The last block ends mid-byte. An empty stored block pads it out,
+        // like zlib's Z_SYNC_FLUSH.
+        if self.bits.count > 0 {
+            self.bits.bits(0, 3);
+            self.bits.align();
+            self.bits.out.extend_from_slice(&[0, 0, 0xFF, 0xFF]);
+            self.inner.write_all(&self.bits.out)?;
+            self.bits.out.clear();
+        }
+        self.inner.flush()
+    }
+}
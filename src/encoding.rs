@@ -0,0 +1,670 @@
+#[cfg(feature = "brotli")]
+use brotli_decompressor::Decompressor as BrotliDecompressor;
+use deflate::Deflater;
+use framing::Framed;
+use inflate::{invalid, Inflater};
+#[cfg(feature = "zstd")]
+use ruzstd::decoding::{BlockDecodingStrategy, FrameDecoder};
+use std::io::{self, BufRead, Read, Write};
+#[cfg(any(feature = "brotli", feature = "zstd"))]
+use std::io::BufReader;
+
+/// The default limit for how large a decoded body may get
+pub const DEFAULT_LIMIT: u64 = 256 * 1024 * 1024;
+
+/// CRC-32 as used by gzip
+pub struct Crc32 {
+    table: [u32; 256],
+    value: u32
+}
+impl Default for Crc32 {
+    fn default() -> Self {
+        let mut table = [0u32; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut c = i as u32;
+            for _ in 0..8 {
+                c = if c & 1 == 1 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+            }
+            *entry = c;
+        }
+        Crc32 {
+            table: table,
+            value: 0
+        }
+    }
+}
+impl Crc32 {
+    pub fn update(&mut self, data: &[u8]) {
+        let mut c = !self.value;
+        for &byte in data {
+            c = self.table[((c ^ byte as u32) & 0xFF) as usize] ^ (c >> 8);
+        }
+        self.value = !c;
+    }
+    pub fn value(&self) -> u32 { self.value }
+}
+
+/// Adler-32 as used by zlib
+pub struct Adler32 {
+    a: u32,
+    b: u32
+}
+impl Default for Adler32 {
+    fn default() -> Self { Adler32 { a: 1, b: 0 } }
+}
+impl Adler32 {
+    pub fn update(&mut self, data: &[u8]) {
+        // 5552 is the most bytes that can be summed before b overflows
+        for chunk in data.chunks(5552) {
+            for &byte in chunk {
+                self.a += byte as u32;
+                self.b += self.a;
+            }
+            self.a %= 65521;
+            self.b %= 65521;
+        }
+    }
+    pub fn value(&self) -> u32 { self.b << 16 | self.a }
+}
+
+/// A supported `Content-Encoding`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// DEFLATE with gzip framing
+    Gzip,
+    /// DEFLATE with zlib framing, like the spec says, or raw DEFLATE, like
+    /// some servers send anyway
+    Deflate,
+    #[cfg(feature = "brotli")]
+    Brotli,
+    #[cfg(feature = "zstd")]
+    Zstd
+}
+impl Encoding {
+    /// Parse a single `Content-Encoding` token
+    pub fn from_token(token: &str) -> Option<Encoding> {
+        match &*token.trim().to_ascii_lowercase() {
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            "deflate" => Some(Encoding::Deflate),
+            #[cfg(feature = "brotli")]
+            "br" => Some(Encoding::Brotli),
+            #[cfg(feature = "zstd")]
+            "zstd" => Some(Encoding::Zstd),
+            _ => None
+        }
+    }
+    /// Parse a `Content-Encoding` header into the encodings in the order they
+    /// were applied. Returns `None` if any of them isn't supported, or if
+    /// there's nothing to decode, like for "identity".
+    pub fn parse_list(value: &[u8]) -> Option<Vec<Encoding>> {
+        let encodings = ::std::str::from_utf8(value).ok()?
+            .split(',')
+            .filter(|token| !token.trim().is_empty() && !token.trim().eq_ignore_ascii_case("identity"))
+            .map(Encoding::from_token)
+            .collect::<Option<Vec<_>>>()?;
+        if encodings.is_empty() { None } else { Some(encodings) }
+    }
+}
+
+/// The value of "Accept-Encoding" listing every supported encoding
+pub fn accept_encoding() -> &'static str {
+    if cfg!(all(feature = "brotli", feature = "zstd")) {
+        "gzip, deflate, br, zstd"
+    } else if cfg!(feature = "brotli") {
+        "gzip, deflate, br"
+    } else if cfg!(feature = "zstd") {
+        "gzip, deflate, zstd"
+    } else {
+        "gzip, deflate"
+    }
+}
+
+/// A reader somewhere in a stack of decoders, which can still reach the
+/// framed body at the bottom.
+pub trait Layer<S: Read>: BufRead {
+    fn stream_ref(&self) -> &Framed<S>;
+    fn stream_mut(&mut self) -> &mut Framed<S>;
+    fn into_stream(self: Box<Self>) -> Framed<S>;
+}
+impl<S: Read> Layer<S> for Framed<S> {
+    fn stream_ref(&self) -> &Framed<S> { self }
+    fn stream_mut(&mut self) -> &mut Framed<S> { self }
+    fn into_stream(self: Box<Self>) -> Framed<S> { *self }
+}
+impl<S: Read> Layer<S> for Box<Layer<S>> {
+    fn stream_ref(&self) -> &Framed<S> { (**self).stream_ref() }
+    fn stream_mut(&mut self) -> &mut Framed<S> { (**self).stream_mut() }
+    fn into_stream(self: Box<Self>) -> Framed<S> { (*self).into_stream() }
+}
+
+/// Put a decoder for `encoding` on top of `inner`
+pub fn decoder<S: Read + 'static>(inner: Box<Layer<S>>, encoding: Encoding, limit: u64) -> Box<Layer<S>> {
+    match encoding {
+        Encoding::Gzip | Encoding::Deflate => Box::new(Decoder::new(inner, encoding, limit)),
+        #[cfg(feature = "brotli")]
+        Encoding::Brotli => Box::new(Limited::new(BufReader::new(BrotliDecompressor::new(inner, 4096)), limit)),
+        #[cfg(feature = "zstd")]
+        Encoding::Zstd => Box::new(Limited::new(BufReader::new(Zstd::new(inner)), limit))
+    }
+}
+
+fn limit_error() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "decoded body exceeds the size limit")
+}
+
+/// Makes sure a decoder doesn't produce more than `limit` bytes
+#[cfg(any(feature = "brotli", feature = "zstd"))]
+struct Limited<R: BufRead> {
+    inner: R,
+    size: u64,
+    limit: u64
+}
+#[cfg(any(feature = "brotli", feature = "zstd"))]
+impl<R: BufRead> Limited<R> {
+    fn new(inner: R, limit: u64) -> Limited<R> {
+        Limited {
+            inner: inner,
+            size: 0,
+            limit: limit
+        }
+    }
+}
+#[cfg(any(feature = "brotli", feature = "zstd"))]
+impl<R: BufRead> Read for Limited<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = {
+            let mut data = self.fill_buf()?;
+            data.read(buf)?
+        };
+        self.consume(read);
+        Ok(read)
+    }
+}
+#[cfg(any(feature = "brotli", feature = "zstd"))]
+impl<R: BufRead> BufRead for Limited<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let len = self.inner.fill_buf()?.len();
+        if self.size + len as u64 > self.limit {
+            return Err(limit_error());
+        }
+        self.inner.fill_buf()
+    }
+    fn consume(&mut self, amt: usize) {
+        self.size += amt as u64;
+        self.inner.consume(amt);
+    }
+}
+
+#[cfg(feature = "brotli")]
+impl<S: Read> Layer<S> for Limited<BufReader<BrotliDecompressor<Box<Layer<S>>>>> {
+    fn stream_ref(&self) -> &Framed<S> { self.inner.get_ref().get_ref().stream_ref() }
+    fn stream_mut(&mut self) -> &mut Framed<S> { self.inner.get_mut().get_mut().stream_mut() }
+    fn into_stream(self: Box<Self>) -> Framed<S> { self.inner.into_inner().into_inner().into_stream() }
+}
+
+/// A zstd decoder which doesn't read the frame header until the body is
+/// first read. It keeps hold of the stream even if the header is invalid,
+/// so the connection can still be taken back.
+#[cfg(feature = "zstd")]
+struct Zstd<R: Read> {
+    inner: R,
+    decoder: Option<FrameDecoder>,
+    /// Why the frame header couldn't be read. Returned by every read.
+    error: Option<String>
+}
+#[cfg(feature = "zstd")]
+impl<R: Read> Zstd<R> {
+    fn new(inner: R) -> Zstd<R> {
+        Zstd {
+            inner: inner,
+            decoder: None,
+            error: None
+        }
+    }
+    fn get_ref(&self) -> &R { &self.inner }
+    fn get_mut(&mut self) -> &mut R { &mut self.inner }
+    fn into_inner(self) -> R { self.inner }
+}
+#[cfg(feature = "zstd")]
+impl<R: Read> Read for Zstd<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.decoder.is_none() && self.error.is_none() {
+            let mut decoder = FrameDecoder::new();
+            match decoder.init(&mut self.inner) {
+                Ok(()) => self.decoder = Some(decoder),
+                Err(err) => self.error = Some(format!("zstd: {}", err))
+            }
+        }
+        let decoder = match self.decoder {
+            Some(ref mut decoder) => decoder,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, self.error.clone().unwrap_or_default()))
+        };
+
+        // Like ruzstd's StreamingDecoder: decoded blocks aren't always
+        // collectable right away, so keep going until there's enough
+        while decoder.can_collect() < buf.len() && !decoder.is_finished() {
+            let needed = buf.len() - decoder.can_collect();
+            decoder.decode_blocks(&mut self.inner, BlockDecodingStrategy::UptoBytes(needed))
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("zstd: {}", err)))?;
+        }
+        decoder.read(buf)
+    }
+}
+#[cfg(feature = "zstd")]
+impl<S: Read> Layer<S> for Limited<BufReader<Zstd<Box<Layer<S>>>>> {
+    fn stream_ref(&self) -> &Framed<S> { self.inner.get_ref().get_ref().stream_ref() }
+    fn stream_mut(&mut self) -> &mut Framed<S> { self.inner.get_mut().get_mut().stream_mut() }
+    fn into_stream(self: Box<Self>) -> Framed<S> { self.inner.into_inner().into_inner().into_stream() }
+}
+
+enum Checksum {
+    Crc32(Crc32),
+    Adler32(Adler32),
+    None
+}
+
+/// Decodes a gzip or deflate encoded stream, verifying checksums and
+/// refusing to produce more than `limit` bytes.
+pub struct Decoder<R: BufRead> {
+    inflater: Inflater<R>,
+    encoding: Encoding,
+    checksum: Checksum,
+    started: bool,
+    finished: bool,
+    size: u64,
+    member_start: u64,
+    limit: u64
+}
+impl<R: BufRead> Decoder<R> {
+    /// Decode `input`
+    pub fn new(input: R, encoding: Encoding, limit: u64) -> Decoder<R> {
+        Decoder {
+            inflater: Inflater::new(input),
+            encoding: encoding,
+            checksum: Checksum::None,
+            started: false,
+            finished: false,
+            size: 0,
+            member_start: 0,
+            limit: limit
+        }
+    }
+
+    pub fn get_ref(&self) -> &R { self.inflater.get_ref() }
+    pub fn get_mut(&mut self) -> &mut R { self.inflater.get_mut() }
+    pub fn into_inner(self) -> R { self.inflater.into_inner() }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0];
+        self.inflater.get_mut().read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+    fn u16_le(&mut self) -> io::Result<u16> {
+        Ok(self.byte()? as u16 | (self.byte()? as u16) << 8)
+    }
+    fn u32_le(&mut self) -> io::Result<u32> {
+        Ok(self.u16_le()? as u32 | (self.u16_le()? as u32) << 16)
+    }
+
+    fn header(&mut self) -> io::Result<()> {
+        match self.encoding {
+            Encoding::Gzip => {
+                if self.byte()? != 0x1F || self.byte()? != 0x8B || self.byte()? != 8 {
+                    return Err(invalid("gzip: invalid header"));
+                }
+                let flags = self.byte()?;
+                // MTIME, XFL and OS
+                for _ in 0..6 {
+                    self.byte()?;
+                }
+                if flags & 0x04 != 0 {
+                    let len = self.u16_le()?;
+                    for _ in 0..len {
+                        self.byte()?;
+                    }
+                }
+                // FNAME and FCOMMENT are zero terminated
+                for &flag in &[0x08, 0x10] {
+                    if flags & flag != 0 {
+                        while self.byte()? != 0 {}
+                    }
+                }
+                if flags & 0x02 != 0 {
+                    self.u16_le()?;
+                }
+                self.checksum = Checksum::Crc32(Crc32::default());
+                self.member_start = self.size;
+            },
+            _ => {
+                let is_zlib = {
+                    let peek = self.inflater.get_mut().fill_buf()?;
+                    peek.len() >= 2
+                        && peek[0] & 0x0F == 8
+                        && peek[0] >> 4 <= 7
+                        && (peek[0] as u16 * 256 + peek[1] as u16) % 31 == 0
+                };
+                if is_zlib {
+                    self.byte()?;
+                    if self.byte()? & 0x20 != 0 {
+                        return Err(invalid("zlib: preset dictionaries are not supported"));
+                    }
+                    self.checksum = Checksum::Adler32(Adler32::default());
+                }
+            }
+        }
+        Ok(())
+    }
+    fn trailer(&mut self) -> io::Result<()> {
+        match self.checksum {
+            Checksum::Crc32(ref crc) => {
+                let (value, size) = (crc.value(), self.size - self.member_start);
+                if self.u32_le()? != value {
+                    return Err(invalid("gzip: CRC-32 mismatch"));
+                }
+                if self.u32_le()? != size as u32 {
+                    return Err(invalid("gzip: size mismatch"));
+                }
+            },
+            Checksum::Adler32(ref adler) => {
+                let value = adler.value();
+                let mut expected = [0; 4];
+                self.inflater.get_mut().read_exact(&mut expected)?;
+                if u32::from_be_bytes(expected) != value {
+                    return Err(invalid("zlib: Adler-32 mismatch"));
+                }
+            },
+            Checksum::None => ()
+        }
+
+        // gzip streams may consist of several members
+        let another = self.encoding == Encoding::Gzip
+            && self.inflater.get_mut().fill_buf()?.first() == Some(&0x1F);
+        if another {
+            self.inflater.reset();
+            self.started = false;
+        } else {
+            self.finished = true;
+        }
+        Ok(())
+    }
+}
+impl<S: Read> Layer<S> for Decoder<Box<Layer<S>>> {
+    fn stream_ref(&self) -> &Framed<S> { self.get_ref().stream_ref() }
+    fn stream_mut(&mut self) -> &mut Framed<S> { self.get_mut().stream_mut() }
+    fn into_stream(self: Box<Self>) -> Framed<S> { self.into_inner().into_stream() }
+}
+impl<R: BufRead> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = {
+            let mut data = self.fill_buf()?;
+            data.read(buf)?
+        };
+        self.consume(read);
+        Ok(read)
+    }
+}
+impl<R: BufRead> BufRead for Decoder<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        loop {
+            if self.finished {
+                return Ok(&[]);
+            }
+            if !self.started {
+                self.header()?;
+                self.started = true;
+            }
+            let len = self.inflater.fill_buf()?.len();
+            if len == 0 {
+                self.trailer()?;
+                continue;
+            }
+            if self.size + len as u64 > self.limit {
+                return Err(limit_error());
+            }
+            return self.inflater.fill_buf();
+        }
+    }
+    fn consume(&mut self, amt: usize) {
+        {
+            let data = &self.inflater.unread()[..amt];
+            match self.checksum {
+                Checksum::Crc32(ref mut crc) => crc.update(data),
+                Checksum::Adler32(ref mut adler) => adler.update(data),
+                Checksum::None => ()
+            }
+        }
+        self.size += amt as u64;
+        self.inflater.consume(amt);
+    }
+}
+
+/// Compresses everything written to it into gzip format, for sending
+/// request bodies with `Content-Encoding: gzip`. Output is written to
+/// `inner` as it's produced, so large bodies don't need to be held in
+/// memory. Call [`finish`](#method.finish) to write the trailer.
+pub struct Encoder<W: Write> {
+    deflater: Deflater<W>,
+    crc: Crc32,
+    size: u32,
+    started: bool
+}
+impl<W: Write> Encoder<W> {
+    /// Compress into `inner`
+    pub fn new(inner: W) -> Encoder<W> {
+        Encoder {
+            deflater: Deflater::new(inner),
+            crc: Crc32::default(),
+            size: 0,
+            started: false
+        }
+    }
+
+    pub fn get_ref(&self) -> &W { self.deflater.get_ref() }
+    pub fn get_mut(&mut self) -> &mut W { self.deflater.get_mut() }
+
+    fn header(&mut self) -> io::Result<()> {
+        if !self.started {
+            self.started = true;
+            // No name, no mtime, unknown OS
+            self.deflater.get_mut().write_all(&[0x1F, 0x8B, 8, 0, 0, 0, 0, 0, 0, 0xFF])?;
+        }
+        Ok(())
+    }
+    /// Write the rest of the compressed data and the trailer, and return
+    /// the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.header()?;
+        let mut inner = self.deflater.finish()?;
+        inner.write_all(&self.crc.value().to_le_bytes())?;
+        inner.write_all(&self.size.to_le_bytes())?;
+        Ok(inner)
+    }
+}
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.header()?;
+        let written = self.deflater.write(buf)?;
+        self.crc.update(&buf[..written]);
+        self.size = self.size.wrapping_add(written as u32);
+        Ok(written)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.header()?;
+        self.deflater.flush()
+    }
+}
+
+/// Compress `data` with gzip
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = Encoder::new(Vec::new());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use response::{Parser, Response};
+    use std::io::{BufReader, Cursor};
+
+    const TEXT: &[u8] = b"minttp minttp minttp, a minimal http library";
+    const GZIP: &[u8] = &[
+        0x1F, 0x8B, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xCB, 0xCD, 0xCC, 0x2B, 0x29, 0x29,
+        0x50, 0xC8, 0x45, 0xA6, 0x74, 0x14, 0x12, 0x41, 0xAC, 0xCC, 0xDC, 0xC4, 0x1C, 0x85, 0x0C, 0x90,
+        0x70, 0x4E, 0x66, 0x52, 0x51, 0x62, 0x51, 0x25, 0x00, 0x03, 0xF1, 0x26, 0x36, 0x2C, 0x00, 0x00,
+        0x00
+    ];
+    const ZLIB: &[u8] = &[
+        0x78, 0xDA, 0xCB, 0xCD, 0xCC, 0x2B, 0x29, 0x29, 0x50, 0xC8, 0x45, 0xA6, 0x74, 0x14, 0x12, 0x41,
+        0xAC, 0xCC, 0xDC, 0xC4, 0x1C, 0x85, 0x0C, 0x90, 0x70, 0x4E, 0x66, 0x52, 0x51, 0x62, 0x51, 0x25,
+        0x00, 0x7A, 0x8D, 0x10, 0xBE
+    ];
+
+    /// `TEXT` compressed with deflate (zlib), then gzip
+    const DEFLATE_GZIP: &[u8] = &[
+        0x1F, 0x8B, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xAB, 0xB8, 0x75, 0xFA, 0xEC, 0x19,
+        0x6D, 0x4D, 0xCD, 0x80, 0x13, 0xAE, 0xCB, 0x4A, 0x44, 0x84, 0x1C, 0xD7, 0x9C, 0xB9, 0x73, 0x44,
+        0xA6, 0x95, 0x67, 0x42, 0x81, 0x5F, 0x5A, 0x50, 0x60, 0x52, 0xA0, 0x2A, 0x43, 0x55, 0xAF, 0xC0,
+        0x3E, 0x00, 0x8C, 0xCA, 0xF6, 0x50, 0x25, 0x00, 0x00, 0x00
+    ];
+    /// `TEXT` compressed with gzip, then brotli
+    #[cfg(feature = "brotli")]
+    const GZIP_BR: &[u8] = &[
+        0x0B, 0x18, 0x80, 0x1F, 0x8B, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xCB, 0xCD, 0xCC,
+        0x2B, 0x29, 0x29, 0x50, 0xC8, 0x45, 0xA6, 0x74, 0x14, 0x12, 0x41, 0xAC, 0xCC, 0xDC, 0xC4, 0x1C,
+        0x85, 0x0C, 0x90, 0x70, 0x4E, 0x66, 0x52, 0x51, 0x62, 0x51, 0x25, 0x00, 0x03, 0xF1, 0x26, 0x36,
+        0x2C, 0x00, 0x00, 0x00, 0x03
+    ];
+    #[cfg(feature = "zstd")]
+    const ZSTD: &[u8] = &[
+        0x28, 0xB5, 0x2F, 0xFD, 0x04, 0x68, 0x1D, 0x01, 0x00, 0xF2, 0xC1, 0x06, 0x0C, 0xE0, 0x6D, 0x23,
+        0x65, 0x7D, 0x5F, 0x68, 0x1B, 0xC0, 0x0E, 0x19, 0x16, 0x89, 0x81, 0x62, 0xEA, 0xFD, 0x53, 0x8A,
+        0xB2, 0xB9, 0x1A, 0x85, 0xDE, 0x6F, 0xAE, 0x01, 0x00, 0x3A, 0x8A, 0x11, 0xA1, 0xDD, 0xB0, 0x8F
+    ];
+
+    fn response(encoding: &str, body: &[u8]) -> Response<Cursor<Vec<u8>>> {
+        let mut raw = format!("HTTP/1.1 200 OK\r\nContent-Encoding: {}\r\n\r\n", encoding).into_bytes();
+        raw.extend_from_slice(body);
+        Response::new(BufReader::new(Cursor::new(raw))).unwrap().decompress(DEFAULT_LIMIT)
+    }
+    fn read_body(mut response: Response<Cursor<Vec<u8>>>) -> Vec<u8> {
+        let mut body = Vec::new();
+        response.body.read_to_end(&mut body).unwrap();
+        body
+    }
+
+    fn decode(input: &[u8], encoding: Encoding, limit: u64) -> io::Result<Vec<u8>> {
+        let mut output = Vec::new();
+        Decoder::new(input, encoding, limit).read_to_end(&mut output)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(decode(GZIP, Encoding::Gzip, DEFAULT_LIMIT).unwrap(), TEXT);
+        assert_eq!(decode(ZLIB, Encoding::Deflate, DEFAULT_LIMIT).unwrap(), TEXT);
+        // Raw DEFLATE, without the zlib header and trailer
+        assert_eq!(decode(&ZLIB[2..ZLIB.len() - 4], Encoding::Deflate, DEFAULT_LIMIT).unwrap(), TEXT);
+
+        let mut twice = GZIP.to_vec();
+        twice.extend_from_slice(GZIP);
+        assert_eq!(decode(&twice, Encoding::Gzip, DEFAULT_LIMIT).unwrap(), [TEXT, TEXT].concat());
+    }
+    #[test]
+    fn stacked() {
+        let res = response("deflate, gzip", DEFLATE_GZIP);
+        assert!(res.header("Content-Encoding").is_none());
+        assert_eq!(read_body(res), TEXT);
+
+        // Unknown encodings leave the body untouched
+        let res = response("gzip, compress", GZIP);
+        assert_eq!(res.header("Content-Encoding"), Some(&b"gzip, compress"[..]));
+        assert_eq!(read_body(res), GZIP);
+
+        // So does "identity", which isn't an encoding at all
+        let raw = b"HTTP/1.1 200 OK\r\nContent-Encoding: identity\r\nContent-Length: 4\r\n\r\ntext".to_vec();
+        let res = Response::new(BufReader::new(Cursor::new(raw))).unwrap().decompress(DEFAULT_LIMIT);
+        assert_eq!(res.header("Content-Encoding"), Some(&b"identity"[..]));
+        assert_eq!(res.header("Content-Length"), Some(&b"4"[..]));
+        assert_eq!(read_body(res), b"text");
+        assert_eq!(Encoding::parse_list(b"identity, "), None);
+
+        #[cfg(feature = "brotli")]
+        assert_eq!(read_body(response("gzip, br", GZIP_BR)), TEXT);
+        #[cfg(feature = "zstd")]
+        assert_eq!(read_body(response("zstd", ZSTD)), TEXT);
+    }
+    #[test]
+    fn empty() {
+        let raw: &[&[u8]] = &[
+            b"HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: 40\r\n\r\n",
+            b"HTTP/1.1 304 Not Modified\r\nContent-Encoding: gzip\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: 0\r\n\r\n"
+        ];
+        for (raw, method) in raw.iter().zip(&["HEAD", "GET", "GET"]) {
+            let res = Parser::new().parse_for(method, BufReader::new(Cursor::new(raw.to_vec()))).unwrap();
+            let res = res.decompress(DEFAULT_LIMIT);
+            assert_eq!(res.header("Content-Encoding"), Some(&b"gzip"[..]));
+            assert_eq!(read_body(res), b"");
+        }
+    }
+    #[test]
+    fn errors() {
+        let mut corrupt = GZIP.to_vec();
+        corrupt[GZIP.len() - 8] ^= 1;
+        assert!(decode(&corrupt, Encoding::Gzip, DEFAULT_LIMIT).is_err());
+
+        let mut corrupt = ZLIB.to_vec();
+        corrupt[ZLIB.len() - 1] ^= 1;
+        assert!(decode(&corrupt, Encoding::Deflate, DEFAULT_LIMIT).is_err());
+
+        assert!(decode(&GZIP[..20], Encoding::Gzip, DEFAULT_LIMIT).is_err());
+        assert!(decode(GZIP, Encoding::Gzip, 10).is_err());
+
+        // A bad zstd header fails every read, but the stream survives
+        #[cfg(feature = "zstd")]
+        {
+            let mut res = response("zstd", b"not zstd");
+            assert!(res.body.read_to_end(&mut Vec::new()).is_err());
+            assert!(res.body.read_to_end(&mut Vec::new()).is_err());
+            assert!(!res.is_reusable());
+            res.body.into_inner();
+        }
+    }
+    #[test]
+    fn encode() {
+        assert_eq!(decode(&gzip(b""), Encoding::Gzip, DEFAULT_LIMIT).unwrap(), b"");
+        assert_eq!(decode(&gzip(TEXT), Encoding::Gzip, DEFAULT_LIMIT).unwrap(), TEXT);
+
+        // Long enough to span several blocks, written in small pieces
+        let mut x = 1u32;
+        let data: Vec<u8> = (0..300_000u32).map(|i| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            if i % 1000 < 500 { TEXT[i as usize % TEXT.len()] } else { x as u8 }
+        }).collect();
+        let mut encoder = Encoder::new(Vec::new());
+        for chunk in data.chunks(777) {
+            encoder.write_all(chunk).unwrap();
+        }
+        encoder.flush().unwrap();
+
+        // Everything written before a flush is in the output, so ending the
+        // stream right there (with an empty final block) gives it all back
+        let decode_flushed = |deflated: &[u8]| {
+            assert!(deflated.ends_with(&[0, 0, 0xFF, 0xFF]));
+            let mut out = Vec::new();
+            Inflater::new(&[deflated, &[0x03, 0x00]].concat()[..]).read_to_end(&mut out).unwrap();
+            out
+        };
+        assert_eq!(decode_flushed(&encoder.get_ref()[10..]), data);
+        encoder.write_all(b"more").unwrap();
+        encoder.flush().unwrap();
+        assert_eq!(&decode_flushed(&encoder.get_ref()[10..])[data.len()..], b"more");
+        let data = [data, b"more".to_vec()].concat();
+        let compressed = encoder.finish().unwrap();
+        assert!(compressed.len() < data.len());
+        assert_eq!(decode(&compressed, Encoding::Gzip, DEFAULT_LIMIT).unwrap(), data);
+    }
+}
@@ -1,34 +1,80 @@
-#[cfg(feature = "native-tls")] use native_tls::Error as TlsError;
-#[cfg(feature = "native-tls")] use native_tls::HandshakeError as TlsHandshakeError;
+#[cfg(feature = "native-tls")] use native_tls;
+#[cfg(feature = "rustls")] use rustls;
+#[cfg(feature = "native-tls")] use std::io::ErrorKind;
 #[cfg(feature = "native-tls")] use std::net::TcpStream;
 use std::error::Error as StdError;
 use std::fmt;
 use std::io::Error as IoError;
 use std::num::ParseIntError;
+use tls::TlsError;
 
 /// minttp error type
 #[derive(Debug)]
 pub enum Error {
+    BodyTooLarge,
+    EmptyResponse,
+    HeaderLineTooLong,
+    HeadersTooLarge,
+    InvalidCassette,
+    InvalidContentLength,
     InvalidHeader,
+    InvalidMethod,
+    InvalidRequest,
+    InvalidRequestLine,
     InvalidStatusLine,
+    InvalidStatusCode,
+    InvalidTransferEncoding,
+    InvalidUrl,
+    NotHttp,
+    RequestLineTooLong,
+    /// None of the server's certificates matched a pinned key
+    PinMismatch,
+    StatusLineTooLong,
+    TooManyHeaders,
+    TooManyInterimResponses,
+    TruncatedStatusLine,
+    UnmatchedRequest,
+    UnsupportedVersion,
+    /// The server picked an ALPN protocol other than HTTP/1.1
+    UnsupportedProtocol,
     IoError(IoError),
     ParseIntError(ParseIntError),
-    #[cfg(feature = "native-tls")]
+    /// The TLS configuration couldn't be used, such as an invalid certificate
     TlsError(TlsError),
-    #[cfg(feature = "native-tls")]
-    TlsHandshakeError(TlsHandshakeError<TcpStream>),
+    /// The TLS handshake failed, such as when the server isn't trusted
+    TlsHandshakeError(TlsError),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
+            Error::BodyTooLarge |
+            Error::EmptyResponse |
+            Error::HeaderLineTooLong |
+            Error::HeadersTooLarge |
+            Error::InvalidCassette |
+            Error::InvalidContentLength |
             Error::InvalidHeader |
-            Error::InvalidStatusLine   => write!(f, "{}", self.description()),
+            Error::InvalidMethod |
+            Error::InvalidRequest |
+            Error::InvalidRequestLine |
+            Error::InvalidStatusLine |
+            Error::InvalidStatusCode |
+            Error::InvalidTransferEncoding |
+            Error::InvalidUrl |
+            Error::NotHttp |
+            Error::RequestLineTooLong |
+            Error::PinMismatch |
+            Error::StatusLineTooLong |
+            Error::TooManyHeaders |
+            Error::TooManyInterimResponses |
+            Error::TruncatedStatusLine |
+            Error::UnmatchedRequest |
+            Error::UnsupportedProtocol |
+            Error::UnsupportedVersion  => write!(f, "{}", self.description()),
             Error::IoError(ref inner)  => write!(f, "{}", inner),
             Error::ParseIntError(ref inner) => write!(f, "{}", inner),
-            #[cfg(feature = "native-tls")]
             Error::TlsError(ref inner) => write!(f, "{}", inner),
-            #[cfg(feature = "native-tls")]
             Error::TlsHandshakeError(ref inner) => write!(f, "{}", inner)
         }
     }
@@ -36,13 +82,33 @@ impl fmt::Display for Error {
 impl StdError for Error {
     fn description(&self) -> &str {
         match *self {
+            Error::BodyTooLarge        => "Request parsing error: Body too large",
+            Error::EmptyResponse       => "Response parsing error: Connection closed without a response",
+            Error::HeaderLineTooLong   => "Response parsing error: Header line too long",
+            Error::HeadersTooLarge     => "Response parsing error: Headers too large",
+            Error::InvalidCassette     => "Cassette error: Invalid cassette file",
+            Error::InvalidContentLength => "Response parsing error: Invalid or conflicting Content-Length",
             Error::InvalidHeader       => "Response parsing error: Invalid header",
+            Error::InvalidMethod       => "Request error: Invalid method",
+            Error::InvalidRequest      => "Request error: Invalid character in method, path, version or header",
+            Error::InvalidRequestLine  => "Request parsing error: Invalid request line",
             Error::InvalidStatusLine   => "Response parsing error: Invalid status line",
+            Error::InvalidStatusCode   => "Response parsing error: Invalid status code",
+            Error::InvalidTransferEncoding => "Response parsing error: Unsupported or invalid Transfer-Encoding",
+            Error::InvalidUrl          => "Conversion error: URL can't be represented",
+            Error::NotHttp             => "Response parsing error: Not an HTTP response",
+            Error::RequestLineTooLong  => "Request parsing error: Request line too long",
+            Error::PinMismatch         => "TLS error: No certificate matched the pinned keys",
+            Error::StatusLineTooLong   => "Response parsing error: Status line too long",
+            Error::TooManyHeaders      => "Response parsing error: Too many headers",
+            Error::TooManyInterimResponses => "Response parsing error: Too many interim responses",
+            Error::TruncatedStatusLine => "Response parsing error: Status line ended early",
+            Error::UnmatchedRequest    => "Cassette error: No recorded response matches the request",
+            Error::UnsupportedVersion  => "Conversion error: HTTP version not supported",
+            Error::UnsupportedProtocol => "TLS error: Server negotiated a protocol other than HTTP/1.1",
             Error::IoError(ref inner)  => inner.description(),
             Error::ParseIntError(ref inner) => inner.description(),
-            #[cfg(feature = "native-tls")]
             Error::TlsError(ref inner) => inner.description(),
-            #[cfg(feature = "native-tls")]
             Error::TlsHandshakeError(ref inner) => inner.description()
         }
     }
@@ -50,9 +116,7 @@ impl StdError for Error {
         match *self {
             Error::IoError(ref inner)  => Some(inner),
             Error::ParseIntError(ref inner) => Some(inner),
-            #[cfg(feature = "native-tls")]
             Error::TlsError(ref inner) => Some(inner),
-            #[cfg(feature = "native-tls")]
             Error::TlsHandshakeError(ref inner) => Some(inner),
             _ => None
         }
@@ -64,11 +128,27 @@ impl From<IoError> for Error {
 impl From<ParseIntError> for Error {
     fn from(error: ParseIntError) -> Self { Error::ParseIntError(error) }
 }
-#[cfg(feature = "native-tls")]
 impl From<TlsError> for Error {
     fn from(error: TlsError) -> Self { Error::TlsError(error) }
 }
 #[cfg(feature = "native-tls")]
-impl From<TlsHandshakeError<TcpStream>> for Error {
-    fn from(error: TlsHandshakeError<TcpStream>) -> Self { Error::TlsHandshakeError(error) }
+impl From<native_tls::Error> for Error {
+    fn from(error: native_tls::Error) -> Self { Error::TlsError(TlsError::NativeTls(error)) }
+}
+#[cfg(feature = "native-tls")]
+impl From<native_tls::HandshakeError<TcpStream>> for Error {
+    fn from(error: native_tls::HandshakeError<TcpStream>) -> Self {
+        match error {
+            native_tls::HandshakeError::Failure(error) => Error::TlsHandshakeError(TlsError::NativeTls(error)),
+            native_tls::HandshakeError::WouldBlock(_) => Error::IoError(ErrorKind::WouldBlock.into())
+        }
+    }
+}
+#[cfg(feature = "rustls")]
+impl From<rustls::Error> for Error {
+    fn from(error: rustls::Error) -> Self { Error::TlsError(TlsError::Rustls(error)) }
+}
+#[cfg(feature = "rustls")]
+impl From<rustls::pki_types::pem::Error> for Error {
+    fn from(error: rustls::pki_types::pem::Error) -> Self { Error::TlsError(TlsError::Pem(error)) }
 }
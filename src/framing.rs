@@ -0,0 +1,347 @@
+use error::Error;
+use inflate::invalid;
+use response::{read_headers, read_line, trim, Head, Limits};
+use std::cmp;
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+/// Longest chunk size line accepted, including extensions
+const MAX_CHUNK_LINE: usize = 4096;
+
+/// How the end of a response body is found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// There is no body, like for HEAD requests or 304 responses
+    Empty,
+    /// "Content-Length"
+    Length(u64),
+    /// "Transfer-Encoding: chunked"
+    Chunked,
+    /// The body ends when the connection is closed
+    Close
+}
+impl Framing {
+    /// Work out the framing of a response to a `method` request, following
+    /// RFC 9112 section 6.3. Conflicting or malformed framing headers are an
+    /// error. The returned bool is true if the framing was ambiguous but
+    /// could be resolved, like both "Transfer-Encoding" and
+    /// "Content-Length" being sent, in which case the connection must not
+    /// be reused.
+    pub fn of_response(head: &Head, method: &str) -> Result<(Framing, bool), Error> {
+        let status = head.status.as_u16();
+        if method == "HEAD" || head.status.is_informational() || status == 204 || status == 304 {
+            return Ok((Framing::Empty, false));
+        }
+        if method == "CONNECT" && head.status.is_success() {
+            return Ok((Framing::Close, false));
+        }
+
+        let header = |name: &str| head.headers.iter()
+            .find(|&(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, val)| &**val);
+
+        if let Some(value) = header("Transfer-Encoding") {
+            let codings: Vec<String> = String::from_utf8_lossy(value)
+                .split(',')
+                .map(|coding| coding.trim().to_ascii_lowercase())
+                .filter(|coding| !coding.is_empty() && coding != "identity")
+                .collect();
+            if codings != ["chunked"] {
+                return Err(Error::InvalidTransferEncoding);
+            }
+            // Transfer-Encoding wins over Content-Length, and HTTP/1.0 has
+            // no Transfer-Encoding at all, so either means someone along
+            // the way might frame this differently.
+            let ambiguous = header("Content-Length").is_some() || head.http_version == "HTTP/1.0";
+            return Ok((Framing::Chunked, ambiguous));
+        }
+
+        match header("Content-Length") {
+            Some(value) => Ok((Framing::Length(parse_content_length(value)?), false)),
+            None => Ok((Framing::Close, false))
+        }
+    }
+}
+
+/// Parse a "Content-Length" value. Repeated identical values, like
+/// "42, 42", are allowed, but anything else that isn't a plain number is
+/// `Error::InvalidContentLength`.
+pub fn parse_content_length(value: &[u8]) -> Result<u64, Error> {
+    let mut length = None;
+    for part in value.split(|&b| b == b',') {
+        let part = trim(part);
+        if part.is_empty() || !part.iter().all(u8::is_ascii_digit) {
+            return Err(Error::InvalidContentLength);
+        }
+        let mut n: u64 = 0;
+        for &digit in part {
+            n = match n.checked_mul(10).and_then(|n| n.checked_add((digit - b'0') as u64)) {
+                Some(n) => n,
+                None => return Err(Error::InvalidContentLength)
+            };
+        }
+        if length.map_or(false, |length| length != n) {
+            return Err(Error::InvalidContentLength);
+        }
+        length = Some(n);
+    }
+    length.ok_or(Error::InvalidContentLength)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// This many bytes of the body or the current chunk are left
+    Remaining(u64),
+    ChunkSize,
+    ChunkEnd,
+    Trailers,
+    Close,
+    Done
+}
+
+/// Reads exactly one body from a connection, according to its
+/// [`Framing`](enum.Framing.html). Anything after the body is left unread.
+pub struct Framed<S: Read> {
+    inner: BufReader<S>,
+    framing: Framing,
+    state: State,
+    ambiguous: bool
+}
+impl<S: Read> Framed<S> {
+    /// Read a body framed with `framing` from `inner`. See
+    /// [`Framing::of_response`](enum.Framing.html#method.of_response) for
+    /// `ambiguous`.
+    pub fn new(inner: BufReader<S>, framing: Framing, ambiguous: bool) -> Framed<S> {
+        Framed {
+            inner: inner,
+            framing: framing,
+            state: match framing {
+                Framing::Empty => State::Done,
+                Framing::Length(len) => State::Remaining(len),
+                Framing::Chunked => State::ChunkSize,
+                Framing::Close => State::Close
+            },
+            ambiguous: ambiguous
+        }
+    }
+
+    pub fn framing(&self) -> Framing { self.framing }
+    /// True once the whole body has been read
+    pub fn is_done(&self) -> bool { self.state == State::Done }
+    /// True if the body has been read to the end and the connection is in a
+    /// known state, so another response could be read from it
+    pub fn is_reusable(&self) -> bool {
+        self.is_done() && !self.ambiguous && self.framing != Framing::Close
+    }
+
+    /// The connection. Reading from it directly skips the framing.
+    pub fn get_ref(&self) -> &BufReader<S> { &self.inner }
+    pub fn get_mut(&mut self) -> &mut BufReader<S> { &mut self.inner }
+    pub fn into_inner(self) -> BufReader<S> { self.inner }
+
+    /// Move on until there's body data to read or the body is done
+    fn advance(&mut self) -> io::Result<()> {
+        let mut line = Vec::new();
+        loop {
+            self.state = match self.state {
+                State::Remaining(0) if self.framing == Framing::Chunked => State::ChunkEnd,
+                State::Remaining(0) => State::Done,
+                State::ChunkSize => {
+                    line.clear();
+                    if read_line(&mut self.inner, &mut line, MAX_CHUNK_LINE)? > MAX_CHUNK_LINE {
+                        return Err(invalid("chunked: chunk size line too long"));
+                    }
+                    match parse_chunk_size(&line)? {
+                        0 => State::Trailers,
+                        size => State::Remaining(size)
+                    }
+                },
+                State::ChunkEnd => {
+                    line.clear();
+                    read_line(&mut self.inner, &mut line, 2)?;
+                    if line != b"\r\n" && line != b"\n" {
+                        return Err(invalid("chunked: missing CRLF after chunk"));
+                    }
+                    State::ChunkSize
+                },
+                State::Trailers => {
+                    read_headers(&mut self.inner, &Limits::default()).map_err(|err| match err {
+                        Error::IoError(err) => err,
+                        _ => invalid("chunked: invalid trailer")
+                    })?;
+                    State::Done
+                },
+                State::Remaining(_) | State::Close | State::Done => return Ok(())
+            };
+        }
+    }
+}
+
+fn parse_chunk_size(line: &[u8]) -> io::Result<u64> {
+    if !line.ends_with(b"\n") {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "chunked: connection closed in chunk size"));
+    }
+    let end = line.iter().position(|&b| b == b';' || b == b'\r' || b == b'\n').unwrap();
+    let size = trim(&line[..end]);
+    if size.is_empty() || size.len() > 16 || !size.iter().all(u8::is_ascii_hexdigit) {
+        return Err(invalid("chunked: invalid chunk size"));
+    }
+    let size = ::std::str::from_utf8(size).unwrap();
+    Ok(u64::from_str_radix(size, 16).unwrap())
+}
+
+impl<S: Read> Read for Framed<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = {
+            let mut data = self.fill_buf()?;
+            data.read(buf)?
+        };
+        self.consume(read);
+        Ok(read)
+    }
+}
+impl<S: Read> BufRead for Framed<S> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.advance()?;
+        match self.state {
+            State::Remaining(left) => {
+                let data = self.inner.fill_buf()?;
+                if data.is_empty() {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed before the end of the body"));
+                }
+                Ok(&data[..cmp::min(data.len() as u64, left) as usize])
+            },
+            State::Close => {
+                if self.inner.fill_buf()?.is_empty() {
+                    self.state = State::Done;
+                }
+                self.inner.fill_buf()
+            },
+            _ => Ok(&[])
+        }
+    }
+    fn consume(&mut self, amt: usize) {
+        match self.state {
+            State::Remaining(left) => {
+                let amt = cmp::min(amt as u64, left);
+                self.inner.consume(amt as usize);
+                self.state = State::Remaining(left - amt);
+            },
+            State::Close => self.inner.consume(amt),
+            _ => ()
+        }
+    }
+}
+
+/// Writes a body with "Transfer-Encoding: chunked", one chunk per write.
+/// Call [`finish`](#method.finish) to write the last chunk.
+pub struct Chunked<W: Write> {
+    inner: W
+}
+impl<W: Write> Chunked<W> {
+    pub fn new(inner: W) -> Chunked<W> {
+        Chunked {
+            inner: inner
+        }
+    }
+
+    pub fn get_ref(&self) -> &W { &self.inner }
+    pub fn get_mut(&mut self) -> &mut W { &mut self.inner }
+
+    /// Write the last chunk, with no trailers, and return the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(b"0\r\n\r\n")?;
+        Ok(self.inner)
+    }
+}
+impl<W: Write> Write for Chunked<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // An empty chunk would end the body
+        if !buf.is_empty() {
+            write!(self.inner, "{:X}\r\n", buf.len())?;
+            self.inner.write_all(buf)?;
+            self.inner.write_all(b"\r\n")?;
+        }
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> { self.inner.flush() }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use response::Parser;
+    use std::io::Cursor;
+
+    fn head(headers: &[(&str, &str)]) -> Head {
+        Head {
+            http_version: "HTTP/1.1".to_string(),
+            status: ::StatusCode::OK,
+            description: "OK".to_string(),
+            headers: headers.iter().map(|&(k, v)| (k.to_string(), v.as_bytes().to_vec())).collect()
+        }
+    }
+    fn framing(headers: &[(&str, &str)]) -> Result<(Framing, bool), Error> {
+        Framing::of_response(&head(headers), "GET")
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(framing(&[]).unwrap(), (Framing::Close, false));
+        assert_eq!(framing(&[("Content-Length", "42")]).unwrap(), (Framing::Length(42), false));
+        assert_eq!(framing(&[("content-length", "42, 42")]).unwrap(), (Framing::Length(42), false));
+        assert_eq!(framing(&[("Transfer-Encoding", "Chunked")]).unwrap(), (Framing::Chunked, false));
+        assert_eq!(
+            framing(&[("Transfer-Encoding", "chunked"), ("Content-Length", "42")]).unwrap(),
+            (Framing::Chunked, true)
+        );
+        assert_eq!(Framing::of_response(&head(&[("Content-Length", "42")]), "HEAD").unwrap(), (Framing::Empty, false));
+
+        for bad in &["42, 43", "-1", "+42", "0x10", "", "4 2", "99999999999999999999"] {
+            match framing(&[("Content-Length", bad)]) { Err(Error::InvalidContentLength) => (), _ => panic!("{:?}", bad) }
+        }
+        for bad in &["gzip", "chunked, chunked", "chunked, gzip", "gzip, chunked", ""] {
+            match framing(&[("Transfer-Encoding", bad)]) { Err(Error::InvalidTransferEncoding) => (), _ => panic!("{:?}", bad) }
+        }
+    }
+
+    fn body(raw: &[u8]) -> (io::Result<Vec<u8>>, bool, Vec<u8>) {
+        let mut res = Parser::new().parse(BufReader::new(Cursor::new(raw.to_vec()))).unwrap();
+        let mut body = Vec::new();
+        let result = res.body.read_to_end(&mut body).map(|_| body);
+        let reusable = res.is_reusable();
+        let mut rest = Vec::new();
+        res.body.get_mut().read_to_end(&mut rest).unwrap();
+        (result, reusable, rest)
+    }
+
+    #[test]
+    fn bodies() {
+        let (body_, reusable, rest) = body(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhelloHTTP/1.1 200 OK");
+        assert_eq!((body_.unwrap(), reusable, rest), (b"hello".to_vec(), true, b"HTTP/1.1 200 OK".to_vec()));
+
+        let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+                    5;name=value\r\nhello\r\n1\r\n \r\n5\r\nworld\r\n0\r\nX-Trailer: 1\r\n\r\nnext";
+        let (body_, reusable, rest) = body(raw);
+        assert_eq!((body_.unwrap(), reusable, rest), (b"hello world".to_vec(), true, b"next".to_vec()));
+
+        // Transfer-Encoding wins, but the connection can't be trusted anymore
+        let raw = b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nhi\r\n0\r\n\r\n";
+        let (body_, reusable, _) = body(raw);
+        assert_eq!((body_.unwrap(), reusable), (b"hi".to_vec(), false));
+
+        let (body_, reusable, _) = body(b"HTTP/1.1 200 OK\r\n\r\nuntil close");
+        assert_eq!((body_.unwrap(), reusable), (b"until close".to_vec(), false));
+
+        assert!(body(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort").0.is_err());
+        assert!(body(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n").0.is_err());
+        assert!(body(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nhiX\r\n0\r\n\r\n").0.is_err());
+        assert!(body(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhi").0.is_err());
+
+        // Conflicting lengths on separate lines are caught too
+        let raw = b"HTTP/1.1 200 OK\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab";
+        match Parser::new().parse(BufReader::new(Cursor::new(raw.to_vec()))) {
+            Err(Error::InvalidContentLength) => (),
+            _ => panic!()
+        }
+    }
+}
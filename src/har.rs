@@ -0,0 +1,323 @@
+use date::iso8601;
+use error::Error;
+use response::{split_values, Body, Response};
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use timing::Timings;
+use tls;
+use {finish_response, send, with_diy_request, HttpStream, Request};
+
+/// One recorded request/response pair
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub started: SystemTime,
+    pub timings: Timings,
+    pub method: String,
+    pub url: String,
+    pub query: Option<String>,
+    pub request_headers: Vec<(String, Vec<u8>)>,
+    /// The body as it was sent, after compression
+    pub request_body: Option<Vec<u8>>,
+    pub http_version: String,
+    pub status: u16,
+    pub description: String,
+    pub response_headers: Vec<(String, Vec<u8>)>,
+    pub response_body: Vec<u8>
+}
+
+/// Performs requests while capturing them, so they can be exported as a
+/// HAR 1.2 file and opened in browser devtools.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Recorder {
+    pub entries: Vec<Entry>
+}
+impl Recorder {
+    /// Create an empty recorder
+    pub fn new() -> Recorder { Recorder::default() }
+
+    /// Like [`request`](../fn.request.html), but the exchange is recorded.
+    /// The response body is read into memory in the process.
+    pub fn request(&mut self, req: &Request) -> Result<Response<HttpStream>, Error> {
+        let started = SystemTime::now();
+        let mut method = String::new();
+        let mut request_headers = Vec::new();
+        let mut request_body = None;
+
+        let mut timings = Timings::default();
+        let response = with_diy_request(req, |diy| {
+            method = diy.method.to_string();
+            request_headers = diy.headers.iter()
+                .map(|(k, v)| (k.to_string(), v.to_vec()))
+                .collect();
+            // What was sent, which is compressed if `req.compress` is set
+            request_body = diy.body.map(|body| body.to_vec());
+            send(req, diy, &tls::SHARED, &mut timings)
+        })?;
+
+        let mut response = finish_response(req, response);
+        let mut body = Vec::new();
+        response.body.read_to_end(&mut body)?;
+        timings.wait = response.timings.wait;
+        timings.receive = response.body.elapsed().unwrap_or_default();
+
+        self.entries.push(Entry {
+            started: started,
+            timings: timings,
+            method: method,
+            url: req.url.to_string(),
+            query: req.url.query.clone(),
+            request_headers: request_headers,
+            request_body: request_body,
+            http_version: response.http_version.clone(),
+            status: response.status.as_u16(),
+            description: response.description.clone(),
+            response_headers: response.headers.iter()
+                .flat_map(|(k, v)| split_values(v).into_iter().map(move |v| (k.clone(), v.to_vec())))
+                .collect(),
+            response_body: body.clone()
+        });
+
+        Ok(Response {
+            http_version: response.http_version,
+            status: response.status,
+            description: response.description,
+            headers: response.headers,
+            timings: timings,
+            body: Body::new(BufReader::new(HttpStream::Memory(Cursor::new(body))))
+        })
+    }
+
+    /// Serialize all entries as HAR 1.2 JSON
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        out.push_str("{\"log\":{\"version\":\"1.2\",\"creator\":{\"name\":\"minttp\",\"version\":");
+        json_str(&mut out, env!("CARGO_PKG_VERSION"));
+        out.push_str("},\"entries\":[");
+
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            entry_json(&mut out, entry);
+        }
+
+        out.push_str("]}}");
+        out
+    }
+    /// Write the HAR JSON to `writer`
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(self.to_json().as_bytes())
+    }
+    /// Write the HAR JSON to a file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write(&mut File::create(path)?)
+    }
+}
+
+fn entry_json(out: &mut String, entry: &Entry) {
+    let t = &entry.timings;
+
+    out.push_str("{\"startedDateTime\":");
+    json_str(out, &iso8601(entry.started));
+    write!(out, ",\"time\":{}", millis(t.total())).unwrap();
+
+    out.push_str(",\"request\":{\"method\":");
+    json_str(out, &entry.method);
+    out.push_str(",\"url\":");
+    json_str(out, &entry.url);
+    out.push_str(",\"httpVersion\":\"HTTP/1.1\",\"cookies\":[],\"headers\":");
+    headers_json(out, &entry.request_headers);
+    out.push_str(",\"queryString\":[");
+    if let Some(ref query) = entry.query {
+        for (i, pair) in query.split('&').enumerate() {
+            let mut parts = pair.splitn(2, '=');
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"name\":");
+            json_str(out, parts.next().unwrap());
+            out.push_str(",\"value\":");
+            json_str(out, parts.next().unwrap_or_default());
+            out.push('}');
+        }
+    }
+    out.push(']');
+    if let Some(ref body) = entry.request_body {
+        out.push_str(",\"postData\":{\"mimeType\":");
+        json_str(out, &mime_type(&entry.request_headers));
+        body_json(out, body, "_encoding");
+        out.push('}');
+    }
+    write!(
+        out,
+        ",\"headersSize\":-1,\"bodySize\":{}}}",
+        entry.request_body.as_ref().map(|b| b.len()).unwrap_or_default()
+    ).unwrap();
+
+    write!(out, ",\"response\":{{\"status\":{},\"statusText\":", entry.status).unwrap();
+    json_str(out, &entry.description);
+    out.push_str(",\"httpVersion\":");
+    json_str(out, &entry.http_version);
+    out.push_str(",\"cookies\":[],\"headers\":");
+    headers_json(out, &entry.response_headers);
+    write!(out, ",\"content\":{{\"size\":{},\"mimeType\":", entry.response_body.len()).unwrap();
+    json_str(out, &mime_type(&entry.response_headers));
+    body_json(out, &entry.response_body, "encoding");
+    write!(
+        out,
+        "}},\"redirectURL\":\"\",\"headersSize\":-1,\"bodySize\":{}}}",
+        entry.response_body.len()
+    ).unwrap();
+
+    write!(
+        out,
+        ",\"cache\":{{}},\"timings\":{{\"blocked\":-1,\"dns\":{},\"connect\":{},\"ssl\":{},\"send\":{},\"wait\":{},\"receive\":{}}}}}",
+        millis(t.dns),
+        // HAR includes the TLS handshake in "connect"
+        millis(t.connect + t.tls.unwrap_or_default()),
+        t.tls.map(millis).unwrap_or(-1.0),
+        millis(t.send),
+        millis(t.wait),
+        millis(t.receive)
+    ).unwrap();
+}
+fn headers_json(out: &mut String, headers: &[(String, Vec<u8>)]) {
+    out.push('[');
+    for (i, &(ref name, ref value)) in headers.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str("{\"name\":");
+        json_str(out, name);
+        out.push_str(",\"value\":");
+        json_str(out, &String::from_utf8_lossy(value));
+        out.push('}');
+    }
+    out.push(']');
+}
+fn body_json(out: &mut String, body: &[u8], encoding_key: &str) {
+    out.push_str(",\"text\":");
+    match ::std::str::from_utf8(body) {
+        Ok(text) => json_str(out, text),
+        Err(_) => {
+            json_str(out, &base64(body));
+            write!(out, ",\"{}\":\"base64\"", encoding_key).unwrap();
+        }
+    }
+}
+fn mime_type(headers: &[(String, Vec<u8>)]) -> String {
+    headers.iter()
+        .find(|&&(ref name, _)| name.eq_ignore_ascii_case("Content-Type"))
+        .map(|&(_, ref value)| String::from_utf8_lossy(value).into_owned())
+        .unwrap_or_default()
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+}
+
+fn json_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+}
+
+/// Standard base64 with padding
+pub fn base64(input: &[u8]) -> String {
+    const TABLE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut output = String::with_capacity((input.len() + 2) / 3 * 4);
+
+    for chunk in input.chunks(3) {
+        let n = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(TABLE[(n >> (18 - i * 6)) as usize & 0x3F] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use encoding::gzip;
+    use server;
+    use std::net::TcpListener;
+    use std::thread;
+    use Method;
+
+    #[test]
+    fn record() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || server::serve(listener, |req, res| {
+            res.status(201, "Created").header("Content-Type", "application/octet-stream").header("X-Length", req.body.len().to_string());
+            res.write_all(&[0xFF, 0x00, 0xFE]).unwrap();
+        }));
+
+        let url = format!("http://127.0.0.1:{}/upload?a=1&b", port);
+        let req = Request::new(url.parse().unwrap()).method(Method::Post).body(b"hello".to_vec()).compress(true);
+        let mut recorder = Recorder::new();
+        let mut body = Vec::new();
+        recorder.request(&req).unwrap().body.read_to_end(&mut body).unwrap();
+        assert_eq!(body, [0xFF, 0x00, 0xFE]);
+
+        let entry = &recorder.entries[0];
+        assert_eq!((&*entry.method, &*entry.url, entry.query.as_ref().map(|q| &**q)), ("POST", &*url, Some("a=1&b")));
+        assert_eq!(entry.request_body, Some(gzip(b"hello")));
+        assert!(entry.request_headers.contains(&("Content-Encoding".to_string(), b"gzip".to_vec())));
+        let length = entry.request_body.as_ref().unwrap().len().to_string();
+        assert!(entry.request_headers.contains(&("Content-Length".to_string(), length.clone().into_bytes())));
+        assert_eq!((entry.status, &*entry.description), (201, "Created"));
+        assert!(entry.response_headers.contains(&("X-Length".to_string(), length.clone().into_bytes())));
+        assert_eq!(entry.timings.tls, None);
+        assert!(entry.timings.total() > Duration::from_secs(0));
+
+        let json = recorder.to_json();
+        for part in &[
+            format!("\"method\":\"POST\",\"url\":\"{}\"", url),
+            "\"queryString\":[{\"name\":\"a\",\"value\":\"1\"},{\"name\":\"b\",\"value\":\"\"}]".to_string(),
+            format!("\"_encoding\":\"base64\"}},\"headersSize\":-1,\"bodySize\":{}}}", length),
+            "\"status\":201,\"statusText\":\"Created\"".to_string(),
+            format!("{{\"name\":\"X-Length\",\"value\":\"{}\"}}", length),
+            "\"mimeType\":\"application/octet-stream\",\"text\":\"/wD+\",\"encoding\":\"base64\"".to_string(),
+            "\"timings\":{\"blocked\":-1,\"dns\":".to_string(),
+            "\"ssl\":-1,".to_string()
+        ] {
+            assert!(json.contains(&**part), "{} not in {}", part, json);
+        }
+    }
+
+    #[test]
+    fn encoding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64(&[0xFF, 0x00, 0xFE]), "/wD+");
+
+        let mut out = String::new();
+        json_str(&mut out, "a\"b\\c\n\u{1}");
+        assert_eq!(out, r#""a\"b\\c\n\u0001""#);
+    }
+}
@@ -0,0 +1,338 @@
+use std::io::{self, BufRead, Read};
+
+/// How far back a DEFLATE back-reference may reach
+const WINDOW: usize = 32768;
+/// How much output to decode at a time
+const CHUNK: usize = 16384;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13
+];
+/// The order code length code lengths are sent in
+const CLEN_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+pub(crate) fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Reads single bits, least significant first, from a byte stream
+struct Bits<R: BufRead> {
+    input: R,
+    buf: u32,
+    count: u32
+}
+impl<R: BufRead> Bits<R> {
+    fn byte(&mut self) -> io::Result<u8> {
+        let byte = match self.input.fill_buf()?.first() {
+            Some(&byte) => byte,
+            None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "deflate stream ended early"))
+        };
+        self.input.consume(1);
+        Ok(byte)
+    }
+    fn bits(&mut self, n: u32) -> io::Result<u32> {
+        while self.count < n {
+            self.buf |= (self.byte()? as u32) << self.count;
+            self.count += 8;
+        }
+        let val = self.buf & ((1 << n) - 1);
+        self.buf >>= n;
+        self.count -= n;
+        Ok(val)
+    }
+    /// Throw away the rest of the current byte
+    fn align(&mut self) {
+        self.buf = 0;
+        self.count = 0;
+    }
+}
+
+/// A canonical Huffman code, stored as the number of codes of each length
+/// and the symbols ordered by code.
+#[derive(Default)]
+struct Huffman {
+    count: [u16; 16],
+    symbol: Vec<u16>
+}
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Huffman> {
+        let mut count = [0u16; 16];
+        for &len in lengths {
+            count[len as usize] += 1;
+        }
+
+        let mut left: i32 = 1;
+        for &n in &count[1..] {
+            left = (left << 1) - n as i32;
+            if left < 0 {
+                return Err(invalid("deflate: over-subscribed huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + count[len];
+        }
+        let mut symbol = vec![0; lengths.len()];
+        for (sym, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbol[offsets[len as usize] as usize] = sym as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+
+        count[0] = 0;
+        Ok(Huffman {
+            count: count,
+            symbol: symbol
+        })
+    }
+    fn decode<R: BufRead>(&self, bits: &mut Bits<R>) -> io::Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= bits.bits(1)? as i32;
+            let count = self.count[len] as i32;
+            if code - first < count {
+                return Ok(self.symbol[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("deflate: invalid huffman code"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Header,
+    Stored(usize),
+    Codes,
+    Done
+}
+
+/// A streaming decoder for raw DEFLATE (RFC 1951) data
+pub struct Inflater<R: BufRead> {
+    bits: Bits<R>,
+    state: State,
+    last: bool,
+    lit: Huffman,
+    dist: Huffman,
+    /// Up to `WINDOW` bytes of history followed by unread output
+    buf: Vec<u8>,
+    pos: usize
+}
+impl<R: BufRead> Inflater<R> {
+    /// Decode the DEFLATE stream `input`
+    pub fn new(input: R) -> Inflater<R> {
+        Inflater {
+            bits: Bits {
+                input: input,
+                buf: 0,
+                count: 0
+            },
+            state: State::Header,
+            last: false,
+            lit: Huffman::default(),
+            dist: Huffman::default(),
+            buf: Vec::new(),
+            pos: 0
+        }
+    }
+    /// Start decoding a new stream from the current position of the input
+    pub fn reset(&mut self) {
+        self.bits.align();
+        self.state = State::Header;
+        self.last = false;
+        self.buf.clear();
+        self.pos = 0;
+    }
+
+    /// True once the final block has been decoded
+    pub fn is_done(&self) -> bool { self.state == State::Done }
+    /// Decoded output which hasn't been consumed yet
+    pub fn unread(&self) -> &[u8] { &self.buf[self.pos..] }
+
+    /// The input. Once the stream is done, this is positioned right after
+    /// the last byte of DEFLATE data.
+    pub fn get_ref(&self) -> &R { &self.bits.input }
+    pub fn get_mut(&mut self) -> &mut R { &mut self.bits.input }
+    pub fn into_inner(self) -> R { self.bits.input }
+
+    fn decode_more(&mut self) -> io::Result<()> {
+        if self.pos > WINDOW {
+            self.buf.drain(..self.pos - WINDOW);
+            self.pos = WINDOW;
+        }
+        while self.buf.len() - self.pos < CHUNK {
+            match self.state {
+                State::Done => break,
+                State::Header => self.block_header()?,
+                State::Stored(0) => self.end_block(),
+                State::Stored(left) => {
+                    let read = {
+                        let input = self.bits.input.fill_buf()?;
+                        if input.is_empty() {
+                            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "deflate stream ended early"));
+                        }
+                        let read = left.min(input.len());
+                        self.buf.extend_from_slice(&input[..read]);
+                        read
+                    };
+                    self.bits.input.consume(read);
+                    self.state = State::Stored(left - read);
+                },
+                State::Codes => self.symbol()?
+            }
+        }
+        Ok(())
+    }
+    fn end_block(&mut self) {
+        self.state = if self.last { State::Done } else { State::Header };
+    }
+    fn block_header(&mut self) -> io::Result<()> {
+        self.last = self.bits.bits(1)? == 1;
+        match self.bits.bits(2)? {
+            0 => {
+                self.bits.align();
+                let len = self.bits.bits(16)?;
+                let nlen = self.bits.bits(16)?;
+                if len != !nlen & 0xFFFF {
+                    return Err(invalid("deflate: stored block length mismatch"));
+                }
+                self.state = State::Stored(len as usize);
+            },
+            1 => {
+                let mut lengths = [0u8; 288 + 30];
+                for (i, len) in lengths.iter_mut().enumerate() {
+                    *len = match i {
+                        0..=143 => 8,
+                        144..=255 => 9,
+                        256..=279 => 7,
+                        280..=287 => 8,
+                        _ => 5
+                    };
+                }
+                self.lit = Huffman::new(&lengths[..288])?;
+                self.dist = Huffman::new(&lengths[288..])?;
+                self.state = State::Codes;
+            },
+            2 => {
+                self.dynamic_tables()?;
+                self.state = State::Codes;
+            },
+            _ => return Err(invalid("deflate: invalid block type"))
+        }
+        Ok(())
+    }
+    fn dynamic_tables(&mut self) -> io::Result<()> {
+        let nlen = self.bits.bits(5)? as usize + 257;
+        let ndist = self.bits.bits(5)? as usize + 1;
+        let ncode = self.bits.bits(4)? as usize + 4;
+        if nlen > 286 || ndist > 30 {
+            return Err(invalid("deflate: too many codes"));
+        }
+
+        let mut clens = [0u8; 19];
+        for &i in &CLEN_ORDER[..ncode] {
+            clens[i] = self.bits.bits(3)? as u8;
+        }
+        let clen = Huffman::new(&clens)?;
+
+        let mut lengths = vec![0u8; nlen + ndist];
+        let mut i = 0;
+        while i < lengths.len() {
+            let sym = clen.decode(&mut self.bits)?;
+            let (len, repeat) = match sym {
+                0..=15 => (sym as u8, 1),
+                16 => match i.checked_sub(1) {
+                    Some(prev) => (lengths[prev], 3 + self.bits.bits(2)?),
+                    None => return Err(invalid("deflate: repeat with no previous length"))
+                },
+                17 => (0, 3 + self.bits.bits(3)?),
+                _ => (0, 11 + self.bits.bits(7)?)
+            };
+            let end = i + repeat as usize;
+            if end > lengths.len() {
+                return Err(invalid("deflate: too many code lengths"));
+            }
+            for len_ref in &mut lengths[i..end] {
+                *len_ref = len;
+            }
+            i = end;
+        }
+        if lengths[256] == 0 {
+            return Err(invalid("deflate: missing end-of-block code"));
+        }
+
+        self.lit = Huffman::new(&lengths[..nlen])?;
+        self.dist = Huffman::new(&lengths[nlen..])?;
+        Ok(())
+    }
+    fn symbol(&mut self) -> io::Result<()> {
+        let sym = self.lit.decode(&mut self.bits)? as usize;
+        if sym < 256 {
+            self.buf.push(sym as u8);
+            return Ok(());
+        }
+        if sym == 256 {
+            self.end_block();
+            return Ok(());
+        }
+
+        let sym = sym - 257;
+        if sym >= LENGTH_BASE.len() {
+            return Err(invalid("deflate: invalid length code"));
+        }
+        let len = LENGTH_BASE[sym] as usize + self.bits.bits(LENGTH_EXTRA[sym] as u32)? as usize;
+
+        let sym = self.dist.decode(&mut self.bits)? as usize;
+        if sym >= DIST_BASE.len() {
+            return Err(invalid("deflate: invalid distance code"));
+        }
+        let dist = DIST_BASE[sym] as usize + self.bits.bits(DIST_EXTRA[sym] as u32)? as usize;
+        if dist > self.buf.len() {
+            return Err(invalid("deflate: distance too far back"));
+        }
+
+        for _ in 0..len {
+            let byte = self.buf[self.buf.len() - dist];
+            self.buf.push(byte);
+        }
+        Ok(())
+    }
+}
+impl<R: BufRead> Read for Inflater<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = {
+            let mut data = self.fill_buf()?;
+            data.read(buf)?
+        };
+        self.consume(read);
+        Ok(read)
+    }
+}
+impl<R: BufRead> BufRead for Inflater<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.buf.len() && self.state != State::Done {
+            self.decode_more()?;
+        }
+        Ok(&self.buf[self.pos..])
+    }
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.buf.len());
+    }
+}
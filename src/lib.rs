@@ -1,51 +1,166 @@
 #[cfg(feature = "native-tls")]
 extern crate native_tls;
+#[cfg(feature = "rustls")]
+extern crate rustls;
+#[cfg(feature = "rustls")]
+extern crate webpki_roots;
 #[cfg(feature = "http")]
 extern crate http;
+#[cfg(feature = "http-body")]
+extern crate http_body;
+#[cfg(feature = "http-body")]
+extern crate bytes;
+#[cfg(feature = "brotli")]
+extern crate brotli_decompressor;
+#[cfg(feature = "zstd")]
+extern crate ruzstd;
 
 #[cfg(feature = "native-tls")]
-use native_tls::{TlsConnector, TlsStream};
+use native_tls::TlsStream;
 use std::collections::HashMap;
-use std::io::{self, BufReader, Write};
-use std::net::TcpStream;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
 
-#[cfg(not(feature = "http"))]
+#[cfg(feature = "http")]
+/// Conversions to and from the http crate
+pub mod bridge;
+/// Record and replay requests for offline tests
+pub mod cassette;
+/// Reusable client with interceptors
+pub mod client;
 /// HTTP Method constants, such as GET, HEAD, et.c
 pub mod consts;
+mod date;
+/// DEFLATE encoder
+pub mod deflate;
+/// Content-Encoding support
+pub mod encoding;
 pub mod error;
+/// HAR (HTTP Archive) recording
+pub mod har;
+/// DEFLATE decoder
+pub mod inflate;
+/// Request methods
+pub mod method;
 /// Response parser
 pub mod response;
-#[cfg(not(feature = "http"))]
+/// Retry policies for the client
+pub mod retry;
+/// Response body framing
+pub mod framing;
+/// Minimal HTTP/1.1 server
+pub mod server;
+mod sha256;
+/// Response status codes
+pub mod status;
+/// Per-phase request timings
+pub mod timing;
+/// TLS configuration
+pub mod tls;
 /// Minimal URL parser
 pub mod url;
+mod x509;
 
+pub use client::Client;
 pub use error::Error;
+pub use method::Method;
+pub use status::StatusCode;
+pub use timing::Timings;
 
-use response::Response;
-#[cfg(not(feature = "http"))]
+use response::{Parser, Response};
+use tls::{Connectors, TlsConfig, TlsInfo};
 use url::Url;
 
-/// A wrapper around either `TcpStream` or `TlsStream` to combine them into one
-/// type.
+/// A wrapper around a `TcpStream` or one of the TLS streams to combine them
+/// into one type.
 pub enum HttpStream {
     Plain(TcpStream),
+    /// An in-memory response, such as one replayed from a
+    /// [`Cassette`](cassette/struct.Cassette.html)
+    Memory(io::Cursor<Vec<u8>>),
     #[cfg(feature = "native-tls")]
-    TLS(TlsStream<TcpStream>)
+    TLS(TlsStream<TcpStream>),
+    #[cfg(feature = "rustls")]
+    Rustls(Box<rustls::StreamOwned<rustls::ClientConnection, TcpStream>>)
 }
 
 macro_rules! perform {
     ($self:expr, $fn:ident) => {
         match *$self {
             HttpStream::Plain(ref mut stream) => stream.$fn(),
+            HttpStream::Memory(ref mut stream) => stream.$fn(),
             #[cfg(feature = "native-tls")]
             HttpStream::TLS(ref mut stream) => stream.$fn(),
+            #[cfg(feature = "rustls")]
+            HttpStream::Rustls(ref mut stream) => stream.$fn(),
         }
     };
     ($self:expr, $fn:ident, $($args:expr),*) => {
         match *$self {
             HttpStream::Plain(ref mut stream) => stream.$fn($($args),*),
+            HttpStream::Memory(ref mut stream) => stream.$fn($($args),*),
             #[cfg(feature = "native-tls")]
             HttpStream::TLS(ref mut stream) => stream.$fn($($args),*),
+            #[cfg(feature = "rustls")]
+            HttpStream::Rustls(ref mut stream) => stream.$fn($($args),*),
+        }
+    }
+}
+impl HttpStream {
+    /// Set the read timeout of the underlying socket. Does nothing for
+    /// in-memory streams.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match *self {
+            HttpStream::Plain(ref stream) => stream.set_read_timeout(timeout),
+            HttpStream::Memory(_) => Ok(()),
+            #[cfg(feature = "native-tls")]
+            HttpStream::TLS(ref stream) => stream.get_ref().set_read_timeout(timeout),
+            #[cfg(feature = "rustls")]
+            HttpStream::Rustls(ref stream) => stream.sock.set_read_timeout(timeout)
+        }
+    }
+    /// The protocol the server picked with ALPN, if any
+    pub fn alpn_protocol(&self) -> Result<Option<String>, Error> {
+        let protocol: Option<Vec<u8>> = match *self {
+            HttpStream::Plain(_) | HttpStream::Memory(_) => None,
+            #[cfg(feature = "native-tls")]
+            HttpStream::TLS(ref stream) => stream.negotiated_alpn()?,
+            #[cfg(feature = "rustls")]
+            HttpStream::Rustls(ref stream) => stream.conn.alpn_protocol().map(|protocol| protocol.to_vec())
+        };
+        Ok(protocol.map(|protocol| String::from_utf8_lossy(&protocol).into_owned()))
+    }
+    /// Details about the TLS connection, or `None` if it isn't one
+    pub fn tls_info(&self) -> Result<Option<TlsInfo>, Error> {
+        match *self {
+            HttpStream::Plain(_) | HttpStream::Memory(_) => Ok(None),
+            // native-tls doesn't say whether the session was resumed
+            #[cfg(feature = "native-tls")]
+            HttpStream::TLS(_) => TlsInfo::new(self.peer_certificates()?, self.alpn_protocol()?, false).map(Some),
+            #[cfg(feature = "rustls")]
+            HttpStream::Rustls(ref stream) => {
+                let resumed = stream.conn.handshake_kind() == Some(rustls::HandshakeKind::Resumed);
+                TlsInfo::new(self.peer_certificates()?, self.alpn_protocol()?, resumed).map(Some)
+            }
+        }
+    }
+    /// The DER encoded certificates the server sent, if this is a TLS
+    /// connection. native-tls only exposes the server's own certificate.
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    pub(crate) fn peer_certificates(&self) -> Result<Vec<Vec<u8>>, Error> {
+        match *self {
+            HttpStream::Plain(_) | HttpStream::Memory(_) => Ok(Vec::new()),
+            #[cfg(feature = "native-tls")]
+            HttpStream::TLS(ref stream) => match stream.peer_certificate()? {
+                Some(cert) => Ok(vec![cert.to_der()?]),
+                None => Ok(Vec::new())
+            },
+            #[cfg(feature = "rustls")]
+            HttpStream::Rustls(ref stream) => {
+                let certs = stream.conn.peer_certificates().unwrap_or_default();
+                Ok(certs.iter().map(|cert| cert.to_vec()).collect())
+            }
         }
     }
 }
@@ -62,6 +177,12 @@ impl io::Read for HttpStream {
     fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> { perform!(self, read_exact, buf) }
 }
 
+/// True if `stream` talks HTTP/1.1, which is all minttp speaks. That's
+/// anything but a TLS stream where ALPN picked something else.
+pub(crate) fn speaks_http1(stream: &HttpStream) -> Result<bool, Error> {
+    Ok(stream.alpn_protocol()?.map_or(true, |protocol| protocol == "http/1.1"))
+}
+
 /// The "do it yourself" request parameters.
 /// See [`diy_request`](fn.diy_request.html)
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -78,19 +199,80 @@ pub struct DIYRequest<'a> {
 /// A minimal http helper.
 /// Literally only opens a TCP connection and serializes.
 pub fn diy_request(req: &DIYRequest) -> Result<HttpStream, Error> {
+    diy_request_timed(req, &mut Timings::default())
+}
+/// Same as [`diy_request`](fn.diy_request.html), but records how long DNS
+/// resolution, connecting, the TLS handshake and sending took.
+pub fn diy_request_timed(req: &DIYRequest, timings: &mut Timings) -> Result<HttpStream, Error> {
+    diy_request_tls(req, &TlsConfig::default(), timings)
+}
+/// Same as [`diy_request_timed`](fn.diy_request_timed.html), but connects
+/// with the settings in `tls`. Connectors are shared like they are for
+/// [`request`](fn.request.html). If ALPN settles on a protocol other than
+/// HTTP/1.1, nothing is sent and the stream is returned as is, ready for
+/// that protocol. See [`HttpStream::alpn_protocol`](enum.HttpStream.html#method.alpn_protocol)
+pub fn diy_request_tls(req: &DIYRequest, tls: &TlsConfig, timings: &mut Timings) -> Result<HttpStream, Error> {
+    diy_request_cached(req, tls, &tls::SHARED, timings)
+}
+/// Same as [`diy_request_tls`](fn.diy_request_tls.html), but reuses the
+/// TLS connector in `connectors`
+pub(crate) fn diy_request_cached(req: &DIYRequest, tls: &TlsConfig, connectors: &Connectors, timings: &mut Timings)
+    -> Result<HttpStream, Error>
+{
+    validate_request(req)?;
+
+    let start = Instant::now();
+    let addrs: Vec<SocketAddr> = (req.host, req.port).to_socket_addrs()?.collect();
+    timings.dns = start.elapsed();
+
+    let start = Instant::now();
+    let stream = TcpStream::connect(&*addrs)?;
+    timings.connect = start.elapsed();
+
     let mut stream = if req.ssl {
-        #[cfg(feature = "native-tls")]
-        {
-            let builder = TlsConnector::builder()?.build()?;
-            let stream = TcpStream::connect((req.host, req.port))?;
-            HttpStream::TLS(builder.connect(req.host, stream)?)
+        let start = Instant::now();
+        let stream = tls.connect(connectors, req.host, stream)?;
+        timings.tls = Some(start.elapsed());
+        if !speaks_http1(&stream)? {
+            return Ok(stream);
         }
-        #[cfg(not(feature = "native-tls"))]
-        panic!("Can't use SSL without the --feature \"native-tls\"");
+        stream
     } else {
-        HttpStream::Plain(TcpStream::connect((req.host, req.port))?)
+        HttpStream::Plain(stream)
     };
 
+    let start = Instant::now();
+    write_request(&mut stream, req)?;
+    timings.send = start.elapsed();
+
+    Ok(stream)
+}
+/// Check that every part of `req` can be written without changing the
+/// meaning of the request, so untrusted input can't smuggle in extra headers
+/// or requests. Returns `Error::InvalidRequest` if not.
+pub fn validate_request(req: &DIYRequest) -> Result<(), Error> {
+    let valid = is_token(req.method)
+        && !req.path.is_empty() && req.path.bytes().all(|b| b > b' ' && b < 0x7F)
+        && !req.http_version.is_empty() && req.http_version.bytes().all(|b| b.is_ascii_digit() || b == b'.')
+        && req.headers.iter().all(|(name, value)| is_token(name) && is_field_value(value));
+    if valid { Ok(()) } else { Err(Error::InvalidRequest) }
+}
+/// True for a valid token, like a method or a header name
+pub(crate) fn is_token(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(method::is_tchar)
+}
+/// True if `value` can be written as a header value without ending the
+/// line it's on
+pub(crate) fn is_field_value(value: &[u8]) -> bool {
+    value.iter().all(|&b| b == b'\t' || (b >= b' ' && b != 0x7F))
+}
+/// Serialize a request to any writer, without opening a connection.
+/// This is what [`diy_request`](fn.diy_request.html) sends over the wire.
+/// "Host" comes first and the other headers are sorted, so the output is
+/// always the same for the same request. Nothing is written if the request doesn't pass
+/// [`validate_request`](fn.validate_request.html).
+pub fn write_request<W: Write>(stream: &mut W, req: &DIYRequest) -> Result<(), Error> {
+    validate_request(req)?;
     write!(
         stream,
         "{} {} HTTP/{}\r\n",
@@ -99,7 +281,9 @@ pub fn diy_request(req: &DIYRequest) -> Result<HttpStream, Error> {
         req.http_version
     )?;
 
-    for (name, value) in req.headers {
+    let mut headers: Vec<_> = req.headers.iter().collect();
+    headers.sort_by_key(|&(name, _)| (!name.eq_ignore_ascii_case("Host"), *name));
+    for (name, value) in headers {
         let mut bytes: Vec<_> = name.as_bytes().to_vec();
         bytes.push(':' as u32 as u8);
         bytes.append(&mut value.to_vec());
@@ -113,32 +297,46 @@ pub fn diy_request(req: &DIYRequest) -> Result<HttpStream, Error> {
 
     if let Some(body) = req.body {
         stream.write_all(body)?;
-        stream.write_all(b"\r\n")?;
     }
 
-    Ok(stream)
+    Ok(())
 }
 
-#[cfg(not(feature = "http"))]
 /// This is a high level web request struct which acts like a wrapper around
 /// [`DIYRequest`](struct.DIYRequest.html).
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Request {
     pub url: Url,
-    pub method: &'static str,
+    pub method: Method,
     pub headers: HashMap<String, Vec<u8>>,
-    pub body: Option<Vec<u8>>
+    pub body: Option<Vec<u8>>,
+    /// If set, compressed bodies are accepted and transparently decoded up
+    /// to this many bytes.
+    pub decompress: Option<u64>,
+    /// Send the body compressed with gzip
+    pub compress: bool,
+    /// If set, send "Expect: 100-continue" and wait this long for the
+    /// server to agree before sending the body
+    pub expect_continue: Option<Duration>,
+    /// How the response is parsed
+    pub parser: Parser,
+    /// How HTTPS connections are verified
+    pub tls: TlsConfig
 }
 
-#[cfg(not(feature = "http"))]
 impl Request {
     /// Create a new Request
     pub fn new(url: Url) -> Request {
         Request {
             url: url,
-            method: consts::GET,
+            method: Method::Get,
             headers: HashMap::new(),
-            body: None
+            body: None,
+            decompress: None,
+            compress: false,
+            expect_continue: None,
+            parser: Parser::default(),
+            tls: TlsConfig::default()
         }
     }
 
@@ -147,8 +345,8 @@ impl Request {
         self.url = url;
         self
     }
-    /// Set the request method. See [`consts`](consts/index.html)
-    pub fn method(mut self, method: &'static str) -> Self {
+    /// Set the request method
+    pub fn method(mut self, method: Method) -> Self {
         self.method = method;
         self
     }
@@ -164,25 +362,206 @@ impl Request {
         self.body = Some(body);
         self
     }
+    /// Send "Accept-Encoding" with every supported encoding and decode
+    /// compressed bodies, refusing to decode more than `limit` bytes. See
+    /// [`encoding::DEFAULT_LIMIT`](encoding/constant.DEFAULT_LIMIT.html)
+    pub fn decompress(mut self, limit: u64) -> Self {
+        self.decompress = Some(limit);
+        self
+    }
+    /// Compress the body with gzip and send "Content-Encoding: gzip". Only
+    /// use this if the server is known to accept it. Does nothing if
+    /// "Content-Encoding" is already set. Streamed bodies are compressed
+    /// too, see [`request_streaming`](fn.request_streaming.html).
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+    /// Send "Expect: 100-continue" and only send the body once the server
+    /// responds with `100 Continue`, or `timeout` passes without an answer.
+    /// If the server answers with a final response instead, the body is
+    /// never sent. Useful for large uploads the server might reject.
+    pub fn expect_continue(mut self, timeout: Duration) -> Self {
+        self.expect_continue = Some(timeout);
+        self
+    }
+    /// Set how the response is parsed, such as strictness or a callback
+    /// for `103 Early Hints`
+    pub fn parser(mut self, parser: Parser) -> Self {
+        self.parser = parser;
+        self
+    }
+    /// Set the TLS options, such as extra root certificates or a client
+    /// certificate
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = tls;
+        self
+    }
 
     /// Shortcut for [`request`](fn.request.html)
     pub fn request(&self) -> Result<Response<HttpStream>, Error> { request(self) }
+    /// Shortcut for [`request_streaming`](fn.request_streaming.html)
+    pub fn request_streaming<R: Read>(&self, body: R) -> Result<Response<HttpStream>, Error> {
+        request_streaming(self, body)
+    }
 }
 
-#[cfg(not(feature = "http"))]
 /// High level wrapper around [`diy_request`](fn.diy_request.html).
 /// Applies important headers, such as "Host", "Connection" and
-/// "Content-Length".
+/// "Content-Length". TLS connectors, and so sessions, are shared with
+/// every other request made without a [`Client`](client/struct.Client.html).
 pub fn request(req: &Request) -> Result<Response<HttpStream>, Error> {
+    request_cached(req, &tls::SHARED)
+}
+/// Same as [`request`](fn.request.html), but reuses the TLS connector in
+/// `connectors`
+pub(crate) fn request_cached(req: &Request, connectors: &Connectors) -> Result<Response<HttpStream>, Error> {
+    let mut timings = Timings::default();
+    let mut response = finish_response(req, with_diy_request(req, |diy| send(req, diy, connectors, &mut timings))?);
+    timings.wait = response.timings.wait;
+    response.timings = timings;
+    Ok(response)
+}
+/// Same as [`request`](fn.request.html), but the body is read from `body`
+/// while it's sent, with "Transfer-Encoding: chunked", so it never has to
+/// be held in memory. With [`Request::compress`](struct.Request.html#method.compress)
+/// it's gzipped on the way. `req.body` and `req.expect_continue` are
+/// ignored. Only use this if the server is known to accept chunked bodies.
+pub fn request_streaming<R: Read>(req: &Request, mut body: R) -> Result<Response<HttpStream>, Error> {
+    let mut timings = Timings::default();
+    let streaming = Request {
+        body: None,
+        expect_continue: None,
+        ..req.clone()
+    };
+    let gzip = req.compress && !req.headers.keys().any(|key| key.eq_ignore_ascii_case("Content-Encoding"));
+    let mut stream = with_diy_request(&streaming, |diy| {
+        let mut headers = diy.headers.clone();
+        headers.insert("Transfer-Encoding", b"chunked");
+        if gzip {
+            headers.insert("Content-Encoding", b"gzip");
+        }
+        open(req, &DIYRequest { headers: &headers, ..diy.clone() }, &tls::SHARED, &mut timings)
+    })?;
+
+    let start = Instant::now();
+    {
+        let mut writer = BufWriter::new(framing::Chunked::new(&mut stream));
+        if gzip {
+            let mut encoder = encoding::Encoder::new(writer);
+            io::copy(&mut body, &mut encoder)?;
+            writer = encoder.finish()?;
+        } else {
+            io::copy(&mut body, &mut writer)?;
+        }
+        writer.into_inner().map_err(io::Error::from)?.finish()?.flush()?;
+    }
+    timings.send += start.elapsed();
+
+    let mut response = finish_response(req, req.parser.parse_for(req.method.as_str(), BufReader::new(stream))?);
+    timings.wait = response.timings.wait;
+    response.timings = timings;
+    Ok(response)
+}
+/// Connect and send `diy`, built from `req`. Fails if the server doesn't
+/// speak HTTP/1.1.
+pub(crate) fn open(req: &Request, diy: &DIYRequest, connectors: &Connectors, timings: &mut Timings) -> Result<HttpStream, Error> {
+    let stream = diy_request_cached(diy, &req.tls, connectors, timings)?;
+    if !speaks_http1(&stream)? {
+        return Err(Error::UnsupportedProtocol);
+    }
+    Ok(stream)
+}
+/// Send `diy`, built from `req`, and parse the response with `req.parser`
+pub(crate) fn send(req: &Request, diy: &DIYRequest, connectors: &Connectors, timings: &mut Timings)
+    -> Result<Response<HttpStream>, Error>
+{
+    let (timeout, body) = match (req.expect_continue, diy.body) {
+        (Some(timeout), Some(body)) => (timeout, body),
+        _ => return req.parser.parse_for(diy.method, BufReader::new(open(req, diy, connectors, timings)?))
+    };
+
+    let head_only = DIYRequest {
+        body: None,
+        ..diy.clone()
+    };
+    let mut stream = BufReader::new(open(req, &head_only, connectors, timings)?);
+    let mut interim = 0;
+    loop {
+        stream.get_ref().set_read_timeout(Some(timeout))?;
+        let result = stream.fill_buf().map(|_| ());
+        stream.get_ref().set_read_timeout(None)?;
+        match result {
+            Ok(()) => (),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut => break,
+            Err(err) => return Err(err.into())
+        }
+
+        let head = req.parser.head(&mut stream)?;
+        if head.status == 100 {
+            break;
+        }
+        if !req.parser.informational(&head) {
+            // The server doesn't want the body
+            return Response::from_head(head, diy.method, stream, Timings::default());
+        }
+        interim += 1;
+        if interim > req.parser.limits.interim_responses {
+            return Err(Error::TooManyInterimResponses);
+        }
+    }
+
+    stream.get_mut().write_all(body)?;
+    stream.get_mut().flush()?;
+    req.parser.parse_for(diy.method, stream)
+}
+/// Apply the parts of `req` that affect how the response is read
+pub(crate) fn finish_response(req: &Request, response: Response<HttpStream>) -> Response<HttpStream> {
+    match req.decompress {
+        Some(limit) => response.decompress(limit),
+        None => response
+    }
+}
+/// True for headers that decide how the request is framed. They're always
+/// generated, since a user's value in another case would be sent next to
+/// the generated one and could disagree with it.
+pub(crate) fn is_framing_header(name: &str) -> bool {
+    ["Connection", "Content-Length", "Transfer-Encoding"].iter().any(|framing| name.eq_ignore_ascii_case(framing))
+}
+/// Build the [`DIYRequest`](struct.DIYRequest.html) that
+/// [`request`](fn.request.html) would send and pass it to `f`.
+pub(crate) fn with_diy_request<T, F>(req: &Request, f: F) -> T
+    where F: FnOnce(&DIYRequest) -> T
+{
     let _body;
+    let host = req.url.host_header();
+    let mut body = req.body.as_ref().map(|vec| &**vec);
+    let compressed;
     let mut headers: HashMap<&str, &[u8]> = HashMap::new();
     for (key, val) in &req.headers {
-        headers.insert(&*key, &*val);
+        if !is_framing_header(key) {
+            headers.insert(&*key, &*val);
+        }
     }
 
-    headers.insert("Host", req.url.host.as_bytes());
+    if !req.headers.keys().any(|key| key.eq_ignore_ascii_case("Host")) {
+        headers.insert("Host", host.as_bytes());
+    }
     headers.insert("Connection", b"close");
-    if let Some(ref body) = req.body {
+    if req.decompress.is_some() && !req.headers.keys().any(|key| key.eq_ignore_ascii_case("Accept-Encoding")) {
+        headers.insert("Accept-Encoding", encoding::accept_encoding().as_bytes());
+    }
+    if req.compress && !req.headers.keys().any(|key| key.eq_ignore_ascii_case("Content-Encoding")) {
+        if let Some(data) = body {
+            compressed = encoding::gzip(data);
+            body = Some(&compressed);
+            headers.insert("Content-Encoding", b"gzip");
+        }
+    }
+    if req.expect_continue.is_some() && body.is_some() {
+        headers.insert("Expect", b"100-continue");
+    }
+    if let Some(body) = body {
         _body = body.len().to_string();
         headers.insert("Content-Length", _body.as_bytes());
     }
@@ -191,83 +570,27 @@ pub fn request(req: &Request) -> Result<Response<HttpStream>, Error> {
         ssl: req.url.protocol == "https",
         host: &req.url.host,
         port: req.url.port,
-        method: req.method,
+        method: req.method.as_str(),
         path: &req.url.fullpath,
         http_version: "1.1",
         headers: &headers,
-        body: req.body.as_ref().map(|vec| &**vec)
+        body: body
     };
 
-    let response = diy_request(&request)?;
-    Response::new(BufReader::new(response))
+    f(&request)
 }
-#[cfg(feature = "http")]
-/// High level wrapper around [`diy_request`](fn.diy_request.html).
-/// Applies important headers, such as "Host", "Connection" and
-/// "Content-Length".
-pub fn request<T: AsRef<[u8]>>(req: &mut http::Request<T>) -> Result<Response<HttpStream>, Error> {
-    let body;
-    let mut headers: HashMap<&str, &[u8]> = HashMap::new();
-    for (key, val) in req.headers() {
-        headers.insert(key.as_str(), val.as_bytes());
-    }
-
-    headers.insert("Host", req.uri().host().unwrap_or_default().as_bytes());
-    headers.insert("Connection", b"close");
-
-    body = req.body().as_ref().len().to_string().into_bytes();
-    headers.insert("Content-Length", &body);
-
-    let ssl = req.uri().scheme().map(|s| s == "https").unwrap_or(false);
-    let path = req.uri().path();
-    let mut fullpath = String::with_capacity(
-        if path.is_empty() { 1 } else { path.len() } + 1 + req.uri().query().map(|s| s.len()).unwrap_or_default()
-    );
-    if path.is_empty() {
-        fullpath.push('/');
-    }
-    fullpath.push_str(req.uri().path());
-    fullpath.push_str(req.uri().query().unwrap_or_default());
-
-    let request = DIYRequest {
-        ssl: ssl,
-        host: &req.uri().host().unwrap_or_default(),
-        port: req.uri().port().unwrap_or(if ssl { 443 } else { 80 }),
-        method: req.method().as_str(),
-        path: &fullpath,
-        http_version: "1.1",
-        headers: &headers,
-        body: Some(req.body().as_ref())
-    };
-
-    let response = diy_request(&request)?;
-    Response::new(BufReader::new(response))
-}
-
 macro_rules! gen_func {
     (nobody $name:ident, $method:ident) => {
-        #[cfg(not(feature = "http"))]
         /// Convenience function around [`request`](fn.request.html)
         pub fn $name(url: Url) -> Result<Response<HttpStream>, Error> {
             request(&Request::new(url).method(consts::$method))
         }
-        #[cfg(feature = "http")]
-        /// Convenience function around [`request`](fn.request.html)
-        pub fn $name(uri: http::Uri) -> Result<Response<HttpStream>, Error> {
-            request(&mut http::Request::builder().uri(uri).method(http::method::$method).body([]).unwrap())
-        }
     };
     (body $name:ident, $method:ident) => {
-        #[cfg(not(feature = "http"))]
         /// Convenience function around [`request`](fn.request.html)
         pub fn $name(url: Url, body: Vec<u8>) -> Result<Response<HttpStream>, Error> {
             request(&Request::new(url).method(consts::$method).body(body))
         }
-        #[cfg(feature = "http")]
-        /// Convenience function around [`request`](fn.request.html)
-        pub fn $name(uri: http::Uri, body: Vec<u8>) -> Result<Response<HttpStream>, Error> {
-            request(&mut http::Request::builder().uri(uri).method(http::method::$method).body(body).unwrap())
-        }
     }
 }
 gen_func!(nobody get, GET);
@@ -278,3 +601,211 @@ gen_func!(nobody delete, DELETE);
 gen_func!(nobody connect, CONNECT);
 gen_func!(nobody trace, TRACE);
 gen_func!(body patch, PATCH);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serve one connection: read the head, maybe send `interim`, then read
+    /// the body if `wants_body` and echo it back.
+    fn serve_once(interim: &'static [u8], wants_body: bool) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut len = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" { break; }
+                if line.to_lowercase().starts_with("content-length:") {
+                    len = line[15..].trim().parse().unwrap();
+                }
+            }
+            let mut stream = stream;
+            stream.write_all(interim).unwrap();
+            if !wants_body {
+                stream.write_all(b"HTTP/1.1 413 Content Too Large\r\nContent-Length: 0\r\n\r\n").unwrap();
+                return;
+            }
+            let mut body = vec![0; len];
+            reader.read_exact(&mut body).unwrap();
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", len).unwrap();
+            stream.write_all(&body).unwrap();
+        });
+        port
+    }
+    fn upload(port: u16) -> Response<HttpStream> {
+        let url = format!("127.0.0.1:{}/", port).parse().unwrap();
+        Request::new(url)
+            .method(Method::Put)
+            .body(b"large upload".to_vec())
+            .expect_continue(Duration::from_millis(200))
+            .request()
+            .unwrap()
+    }
+
+    fn diy<'a>(method: &'a str, path: &'a str, headers: &'a HashMap<&'a str, &'a [u8]>) -> DIYRequest<'a> {
+        DIYRequest {
+            ssl: false,
+            host: "example.com",
+            port: 80,
+            method: method,
+            path: path,
+            http_version: "1.1",
+            headers: headers,
+            body: None
+        }
+    }
+    fn invalid(req: &DIYRequest) {
+        let mut out = Vec::new();
+        match write_request(&mut out, req) {
+            Err(Error::InvalidRequest) => assert!(out.is_empty()),
+            _ => panic!("{:?} was serialized", req)
+        }
+    }
+
+    #[test]
+    fn serialize() {
+        let bodies: &[Option<&[u8]>] = &[None, Some(b""), Some(b"text"), Some(b"\r\n\0binary\xFF\r\n")];
+        let methods = [
+            consts::GET, consts::HEAD, consts::POST, consts::PUT, consts::DELETE,
+            consts::CONNECT, consts::OPTIONS, consts::TRACE, consts::PATCH,
+            Method::extension("PROPFIND").unwrap()
+        ];
+        for method in &methods {
+            for body in bodies {
+                let mut req = Request::new("example.com/path?q=1".parse().unwrap())
+                    .method(method.clone())
+                    .header("X-Custom", "value");
+                req.body = body.map(|body| body.to_vec());
+
+                let mut out = Vec::new();
+                with_diy_request(&req, |diy| write_request(&mut out, diy)).unwrap();
+
+                let mut expected = format!("{} /path?q=1 HTTP/1.1\r\nHost:example.com\r\nConnection:close\r\n", method).into_bytes();
+                if let Some(body) = *body {
+                    expected.extend_from_slice(format!("Content-Length:{}\r\n", body.len()).as_bytes());
+                }
+                expected.extend_from_slice(b"X-Custom:value\r\n\r\n");
+                expected.extend_from_slice(body.unwrap_or_default());
+                assert_eq!(out, expected);
+            }
+        }
+
+        // Two requests back to back on one connection parse as two requests
+        let mut out = Vec::new();
+        let req = Request::new("example.com/".parse().unwrap()).method(Method::Post).body(b"abc".to_vec());
+        with_diy_request(&req, |diy| write_request(&mut out, diy)).unwrap();
+        with_diy_request(&req, |diy| write_request(&mut out, diy)).unwrap();
+        let mut reader = &out[..];
+        for _ in 0..2 {
+            let parsed = server::Request::new(&mut reader).unwrap().unwrap();
+            assert_eq!(parsed.body, b"abc");
+        }
+        assert!(reader.is_empty());
+
+        // The port is included if it isn't the default, and a custom Host
+        // replaces the generated one
+        let mut out = Vec::new();
+        let req = Request::new("[::1]:8080/".parse().unwrap());
+        with_diy_request(&req, |diy| write_request(&mut out, diy)).unwrap();
+        assert!(out.starts_with(b"GET / HTTP/1.1\r\nHost:[::1]:8080\r\n"));
+
+        let mut out = Vec::new();
+        let req = Request::new("example.com:8080/".parse().unwrap()).header("host", "example.org");
+        with_diy_request(&req, |diy| write_request(&mut out, diy)).unwrap();
+        assert!(out.starts_with(b"GET / HTTP/1.1\r\nhost:example.org\r\n"));
+        assert_eq!(String::from_utf8_lossy(&out).to_lowercase().matches("host:").count(), 1);
+
+        // Framing headers can't be overridden in any case
+        let mut out = Vec::new();
+        let req = Request::new("example.com/".parse().unwrap())
+            .method(Method::Post)
+            .header("content-length", "3")
+            .header("connection", "keep-alive")
+            .header("Transfer-Encoding", "chunked")
+            .body(b"hello".to_vec());
+        with_diy_request(&req, |diy| write_request(&mut out, diy)).unwrap();
+        assert_eq!(out, &b"POST / HTTP/1.1\r\nHost:example.com\r\nConnection:close\r\nContent-Length:5\r\n\r\nhello"[..]);
+    }
+    #[test]
+    fn injection() {
+        let headers = HashMap::new();
+        let mut out = Vec::new();
+        write_request(&mut out, &diy("PROPFIND", "/a?b=%20", &headers)).unwrap();
+
+        invalid(&diy("GET /x HTTP/1.1\r\n", "/", &headers));
+        invalid(&diy("", "/", &headers));
+        invalid(&diy("GET", "/a b", &headers));
+        invalid(&diy("GET", "/\r\nX-Injected: 1", &headers));
+        invalid(&diy("GET", "", &headers));
+
+        let bad: &[(&str, &[u8])] = &[
+            ("X-Value", b"a\r\n\r\nGET /admin HTTP/1.1"),
+            ("X-Value", b"a\nb"),
+            ("X-Value", b"a\0b"),
+            ("Bad Name", b"a"),
+            ("X-Name:", b"a"),
+            ("", b"a")
+        ];
+        for &(name, value) in bad {
+            let mut headers = HashMap::new();
+            headers.insert(name, value);
+            invalid(&diy("GET", "/", &headers));
+        }
+    }
+    #[test]
+    fn expect_continue() {
+        let mut body = String::new();
+        let mut res = upload(serve_once(b"HTTP/1.1 100 Continue\r\n\r\n", true));
+        res.body.read_to_string(&mut body).unwrap();
+        assert_eq!((res.status.as_u16(), &*body), (200, "large upload"));
+
+        // No answer, so the body is sent after the timeout
+        assert_eq!(upload(serve_once(b"", true)).status, 200);
+
+        // Rejected before the body was sent
+        assert_eq!(upload(serve_once(b"", false)).status, 413);
+    }
+    #[test]
+    fn streaming() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut head = String::new();
+            while !head.ends_with("\r\n\r\n") {
+                reader.read_line(&mut head).unwrap();
+            }
+            let head = head.to_lowercase();
+            assert!(head.contains("transfer-encoding:chunked\r\n") && !head.contains("content-length"));
+            assert!(head.contains("content-encoding:gzip\r\n"));
+
+            let framed = framing::Framed::new(reader, framing::Framing::Chunked, false);
+            let mut decoder = encoding::Decoder::new(framed, encoding::Encoding::Gzip, encoding::DEFAULT_LIMIT);
+            let mut body = Vec::new();
+            decoder.read_to_end(&mut body).unwrap();
+            assert!(decoder.get_ref().is_done());
+            let mut stream = stream;
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).unwrap();
+            stream.write_all(&body).unwrap();
+        });
+
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let url = format!("127.0.0.1:{}/", port).parse().unwrap();
+        let mut res = Request::new(url)
+            .method(Method::Post)
+            .compress(true)
+            .request_streaming(&*data)
+            .unwrap();
+        let mut body = Vec::new();
+        res.body.read_to_end(&mut body).unwrap();
+        assert_eq!((res.status.as_u16(), body), (200, data));
+    }
+}
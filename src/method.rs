@@ -0,0 +1,107 @@
+use error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// A request method. Anything that isn't one of the standard methods,
+/// like WebDAV's `PROPFIND`, is an `Extension`.
+/// Methods are case-sensitive, so `"get".parse()` is an extension too.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Connect,
+    Options,
+    Trace,
+    Patch,
+    Extension(String)
+}
+impl Method {
+    /// Create a method from its name. The standard names give their own
+    /// variant, so `Method::extension("GET")` is `Method::Get`. Returns
+    /// `Error::InvalidMethod` unless `name` is a valid token.
+    pub fn extension<S: Into<String>>(name: S) -> Result<Method, Error> {
+        let name = name.into();
+        Ok(match &*name {
+            "GET" => Method::Get,
+            "HEAD" => Method::Head,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "DELETE" => Method::Delete,
+            "CONNECT" => Method::Connect,
+            "OPTIONS" => Method::Options,
+            "TRACE" => Method::Trace,
+            "PATCH" => Method::Patch,
+            _ if name.is_empty() || !name.bytes().all(is_tchar) => return Err(Error::InvalidMethod),
+            _ => Method::Extension(name)
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        match *self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Connect => "CONNECT",
+            Method::Options => "OPTIONS",
+            Method::Trace => "TRACE",
+            Method::Patch => "PATCH",
+            Method::Extension(ref name) => name
+        }
+    }
+
+    /// True for methods which are only used to retrieve data (RFC 9110
+    /// section 9.2.1)
+    pub fn is_safe(&self) -> bool {
+        match *self {
+            Method::Get | Method::Head | Method::Options | Method::Trace => true,
+            _ => false
+        }
+    }
+    /// True for methods that can be repeated without changing the outcome
+    pub fn is_idempotent(&self) -> bool {
+        match *self {
+            Method::Put | Method::Delete => true,
+            _ => self.is_safe()
+        }
+    }
+}
+impl FromStr for Method {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Method, Error> { Method::extension(s) }
+}
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str(self.as_str()) }
+}
+impl Default for Method {
+    fn default() -> Self { Method::Get }
+}
+
+/// True for bytes allowed in a token (RFC 9110 section 5.6.2)
+pub fn is_tchar(byte: u8) -> bool {
+    match byte {
+        b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'*' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => true,
+        _ => byte.is_ascii_alphanumeric()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!("PUT".parse::<Method>().unwrap(), Method::Put);
+        assert_eq!(Method::extension("GET").unwrap(), Method::Get);
+        assert_eq!("PROPFIND".parse::<Method>().unwrap().as_str(), "PROPFIND");
+        assert!("get".parse::<Method>().unwrap() != Method::Get);
+        assert!("BAD METHOD".parse::<Method>().is_err());
+        assert!(Method::extension("").is_err());
+        assert!(Method::Delete.is_idempotent() && !Method::Post.is_idempotent());
+    }
+}
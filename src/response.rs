@@ -1,89 +1,637 @@
+use encoding::{self, Encoding, Layer};
 use error::Error;
+use framing::{Framed, Framing};
+use status::StatusCode;
 use std::collections::HashMap;
-#[cfg(feature = "http")]
-use std::io;
-use std::io::{BufRead, BufReader, Read};
+use std::fmt;
+use std::sync::Arc;
+use std::io::{self, BufRead, BufReader, Read};
+use std::time::{Duration, Instant};
+use timing::Timings;
+use tls::TlsInfo;
+use HttpStream;
+
+/// Limits on the size of a response head, so a misbehaving server can't
+/// make the parser use unbounded memory. The [`server`](../server/index.html)
+/// uses them for requests too. Line lengths include the line ending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Longest allowed status line
+    pub status_line: usize,
+    /// Longest allowed header line
+    pub header_line: usize,
+    /// Most headers allowed
+    pub headers: usize,
+    /// Most bytes allowed for all header lines together
+    pub header_bytes: usize,
+    /// Most interim responses, like `103 Early Hints`, allowed before the
+    /// final one
+    pub interim_responses: usize,
+    /// Largest request body the server reads into memory
+    pub request_body: u64,
+    /// How long the server waits on a connection for the next bit of a
+    /// request before closing it, so idle clients can't hold on to it
+    /// forever. `None` waits forever.
+    pub idle_timeout: Option<Duration>
+}
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            status_line: 8192,
+            header_line: 16384,
+            headers: 128,
+            header_bytes: 65536,
+            interim_responses: 16,
+            request_body: 8 * 1024 * 1024,
+            idle_timeout: Some(Duration::from_secs(60))
+        }
+    }
+}
+
+/// Like `BufRead::read_until(b'\n')`, but stops after `limit + 1` bytes so
+/// callers can tell a line was too long without reading all of it.
+pub(crate) fn read_line<R: BufRead>(stream: &mut R, line: &mut Vec<u8>, limit: usize) -> io::Result<usize> {
+    let start = line.len();
+    loop {
+        let (done, used) = {
+            let available = stream.fill_buf()?;
+            let room = (limit + 1).saturating_sub(line.len() - start);
+            let available = &available[..available.len().min(room)];
+            match available.iter().position(|&b| b == b'\n') {
+                Some(i) => {
+                    line.extend_from_slice(&available[..=i]);
+                    (true, i + 1)
+                },
+                None => {
+                    line.extend_from_slice(available);
+                    (available.is_empty(), available.len())
+                }
+            }
+        };
+        stream.consume(used);
+        if done {
+            return Ok(line.len() - start);
+        }
+    }
+}
+
+/// Read header lines up until (and including) the empty line that ends them.
+/// Shared between the response parser and the [`server`](../server/index.html).
+pub(crate) fn read_headers<R: BufRead>(stream: &mut R, limits: &Limits) -> Result<HashMap<String, Vec<u8>>, Error> {
+    let mut headers = HashMap::new();
+    let mut total = 0;
+
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let len = read_line(stream, &mut line, limits.header_line)?;
+        if len > limits.header_line {
+            return Err(Error::HeaderLineTooLong);
+        }
+        total += len;
+        if total > limits.header_bytes {
+            return Err(Error::HeadersTooLarge);
+        }
+
+        let line = trim(&line);
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() >= limits.headers {
+            return Err(Error::TooManyHeaders);
+        }
+
+        let colon = match line.iter().position(|&b| b == b':') {
+            Some(colon) => colon,
+            None => return Err(Error::InvalidHeader)
+        };
+        let name = match ::std::str::from_utf8(trim(&line[..colon])) {
+            Ok(name) => name.to_string(),
+            Err(_) => return Err(Error::InvalidHeader)
+        };
+        let value = trim(&line[colon + 1..]);
+        // Repeated fields are combined into one list (RFC 9110 section 5.3),
+        // except "Set-Cookie", which can't be since cookies contain commas.
+        // Those are kept apart by a newline, which no value can contain.
+        let separator: &[u8] = if name.eq_ignore_ascii_case("Set-Cookie") { b"\n" } else { b", " };
+        match headers.iter_mut().find(|&(key, _): &(&String, &mut Vec<u8>)| key.eq_ignore_ascii_case(&name)) {
+            Some((_, existing)) => {
+                existing.extend_from_slice(separator);
+                existing.extend_from_slice(value);
+            },
+            None => {
+                headers.insert(name, value.to_vec());
+            }
+        }
+    }
+
+    Ok(headers)
+}
+
+/// Split a header value into the fields it was combined from, if it was
+/// one that can't be joined into a list, like "Set-Cookie"
+pub(crate) fn split_values(value: &[u8]) -> Vec<&[u8]> {
+    value.split(|&b| b == b'\n').collect()
+}
+
+/// The body as it's read from the connection, either as is or through one
+/// or more decoders.
+enum Content<Stream: Read> {
+    Identity(Framed<Stream>),
+    Decoded(Box<Layer<Stream>>)
+}
+macro_rules! content {
+    ($self:expr, $inner:ident => $expr:expr) => {
+        match $self.content {
+            Content::Identity(ref mut $inner) => $expr,
+            Content::Decoded(ref mut $inner) => $expr
+        }
+    }
+}
+
+/// The response body. Works like the `BufReader` it wraps, but also keeps
+/// track of how long it took to read, and can transparently decode
+/// compressed bodies.
+pub struct Body<Stream: Read> {
+    content: Content<Stream>,
+    start: Instant,
+    elapsed: Option<Duration>
+}
+impl<Stream: Read> Body<Stream> {
+    /// Wrap a reader positioned at the start of a body that ends when the
+    /// connection is closed
+    pub fn new(inner: BufReader<Stream>) -> Body<Stream> {
+        Body::framed(Framed::new(inner, Framing::Close, false))
+    }
+    /// Wrap a framed body
+    pub fn framed(inner: Framed<Stream>) -> Body<Stream> {
+        Body {
+            content: Content::Identity(inner),
+            start: Instant::now(),
+            elapsed: None
+        }
+    }
+    /// Decode the body with `encoding`, producing at most `limit` bytes.
+    /// Calling this again stacks another decoder on top, so encodings have
+    /// to be decoded in the reverse order they were applied.
+    pub fn decode(self, encoding: Encoding, limit: u64) -> Body<Stream>
+        where Stream: 'static
+    {
+        let inner: Box<Layer<Stream>> = match self.content {
+            Content::Identity(inner) => Box::new(inner),
+            Content::Decoded(inner) => inner
+        };
+        Body {
+            content: Content::Decoded(encoding::decoder(inner, encoding, limit)),
+            start: self.start,
+            elapsed: self.elapsed
+        }
+    }
+    /// Returns true if the body is being decoded
+    pub fn is_decoded(&self) -> bool {
+        match self.content {
+            Content::Identity(_) => false,
+            Content::Decoded(_) => true
+        }
+    }
+    /// How long it took from the end of the headers until the body was read
+    /// to the end, or `None` if it hasn't been yet.
+    pub fn elapsed(&self) -> Option<Duration> { self.elapsed }
+
+    /// The framed, but possibly still encoded, body
+    pub fn framed_ref(&self) -> &Framed<Stream> {
+        match self.content {
+            Content::Identity(ref inner) => inner,
+            Content::Decoded(ref inner) => inner.stream_ref()
+        }
+    }
+    pub fn framed_mut(&mut self) -> &mut Framed<Stream> {
+        match self.content {
+            Content::Identity(ref mut inner) => inner,
+            Content::Decoded(ref mut inner) => inner.stream_mut()
+        }
+    }
+    pub fn into_framed(self) -> Framed<Stream> {
+        match self.content {
+            Content::Identity(inner) => inner,
+            Content::Decoded(inner) => inner.into_stream()
+        }
+    }
+
+    /// The connection. Reading from it directly skips framing and decoding.
+    pub fn get_ref(&self) -> &BufReader<Stream> { self.framed_ref().get_ref() }
+    pub fn get_mut(&mut self) -> &mut BufReader<Stream> { self.framed_mut().get_mut() }
+    pub fn into_inner(self) -> BufReader<Stream> { self.into_framed().into_inner() }
+
+    fn check_eof(&mut self, eof: bool) {
+        if eof && self.elapsed.is_none() {
+            self.elapsed = Some(self.start.elapsed());
+        }
+    }
+}
+impl<Stream: Read> Read for Body<Stream> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = content!(self, inner => inner.read(buf))?;
+        self.check_eof(read == 0 && !buf.is_empty());
+        Ok(read)
+    }
+}
+impl<Stream: Read> BufRead for Body<Stream> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let eof = content!(self, inner => inner.fill_buf())?.is_empty();
+        self.check_eof(eof);
+        content!(self, inner => inner.fill_buf())
+    }
+    fn consume(&mut self, amt: usize) { content!(self, inner => inner.consume(amt)) }
+}
+
+/// The status line and headers of a response, without the body. Used for
+/// interim (1XX) responses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Head {
+    pub http_version: String,
+    pub status: StatusCode,
+    pub description: String,
+    pub headers: HashMap<String, Vec<u8>>
+}
+
+/// Options for parsing the head of a response
+#[derive(Clone, Default)]
+pub struct Parser {
+    /// Follow RFC 9112 to the letter instead of accepting what servers
+    /// commonly get wrong, like bare LF line endings
+    pub strict: bool,
+    pub limits: Limits,
+    informational: Option<Arc<Fn(&Head) + Send + Sync>>
+}
+impl fmt::Debug for Parser {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Parser")
+            .field("strict", &self.strict)
+            .field("limits", &self.limits)
+            .field("informational", &self.informational.is_some())
+            .finish()
+    }
+}
+impl PartialEq for Parser {
+    fn eq(&self, other: &Parser) -> bool {
+        self.strict == other.strict && self.limits == other.limits && match (&self.informational, &other.informational) {
+            (&Some(ref a), &Some(ref b)) => Arc::ptr_eq(a, b),
+            (&None, &None) => true,
+            _ => false
+        }
+    }
+}
+impl Eq for Parser {}
+impl Parser {
+    /// Create a lenient parser
+    pub fn new() -> Parser { Parser::default() }
+
+    /// Enable or disable strict parsing
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+    /// Set the size limits
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+    /// Call `f` with every interim response, such as `103 Early Hints`,
+    /// instead of silently skipping them
+    pub fn on_informational<F: Fn(&Head) + Send + Sync + 'static>(mut self, f: F) -> Self {
+        self.informational = Some(Arc::new(f));
+        self
+    }
+
+    /// Parse a stream into a response struct, assuming it's the response
+    /// to a GET request. See [`parse_for`](#method.parse_for)
+    pub fn parse<Stream: Read>(&self, stream: BufReader<Stream>) -> Result<Response<Stream>, Error> {
+        self.parse_for("GET", stream)
+    }
+    /// Parse a stream into the response to a `method` request, skipping any
+    /// interim responses. `101 Switching Protocols` counts as final. Fails
+    /// with `Error::TooManyInterimResponses` if there are more than
+    /// `limits.interim_responses` of them.
+    pub fn parse_for<Stream: Read>(&self, method: &str, mut stream: BufReader<Stream>) -> Result<Response<Stream>, Error> {
+        let mut timings = Timings::default();
+        let start = Instant::now();
+        stream.fill_buf()?;
+        timings.wait = start.elapsed();
+
+        for _ in 0..=self.limits.interim_responses {
+            let head = self.head(&mut stream)?;
+            if !self.informational(&head) {
+                return Response::from_head(head, method, stream, timings);
+            }
+        }
+        Err(Error::TooManyInterimResponses)
+    }
+    /// If `head` is an interim response, pass it to the callback and return
+    /// true
+    pub fn informational(&self, head: &Head) -> bool {
+        if !head.status.is_informational() || head.status == 101 {
+            return false;
+        }
+        if let Some(ref f) = self.informational {
+            f(head);
+        }
+        true
+    }
+
+    /// Parse a single status line and its headers
+    pub fn head<R: BufRead>(&self, stream: &mut R) -> Result<Head, Error> {
+        let mut line = Vec::new();
+        let mut skipped = 0;
+        loop {
+            line.clear();
+            let limit = self.limits.status_line.saturating_sub(skipped);
+            let len = read_line(stream, &mut line, limit)?;
+            if len == 0 {
+                return Err(Error::EmptyResponse);
+            }
+            if len > limit {
+                return Err(Error::StatusLineTooLong);
+            }
+            // Some servers send stray newlines after a previous response
+            if self.strict || (line != b"\r\n" && line != b"\n") {
+                break;
+            }
+            skipped += len;
+        }
+        let (http_version, status, description) = self.status_line(&line)?;
+
+        Ok(Head {
+            http_version: http_version,
+            status: status,
+            description: description,
+            headers: read_headers(stream, &self.limits)?
+        })
+    }
+
+    /// Parse a status line, including its line ending, into the HTTP
+    /// version, status code and reason phrase. The reason phrase may be
+    /// empty.
+    pub fn status_line(&self, line: &[u8]) -> Result<(String, StatusCode, String), Error> {
+        let line = match (line.ends_with(b"\r\n"), line.ends_with(b"\n")) {
+            (true, _) => &line[..line.len() - 2],
+            (false, true) if !self.strict => &line[..line.len() - 1],
+            (false, true) => return Err(Error::InvalidStatusLine),
+            (false, false) => return Err(Error::TruncatedStatusLine)
+        };
+        let line = if self.strict { line } else { trim(line) };
+
+        let version_end = line.iter().position(|&b| b == b' ' || b == b'\t').unwrap_or(line.len());
+        let (version, rest) = line.split_at(version_end);
+        let valid_version = version.len() == 8 && version[5].is_ascii_digit() && version[6] == b'.' && version[7].is_ascii_digit();
+        let is_http = if self.strict {
+            version.starts_with(b"HTTP/")
+        } else {
+            version.to_ascii_uppercase().starts_with(b"HTTP/")
+        };
+        if !is_http {
+            return Err(Error::NotHttp);
+        }
+        if self.strict && !valid_version {
+            return Err(Error::InvalidStatusLine);
+        }
+
+        let rest = if self.strict {
+            if !rest.starts_with(b" ") {
+                return Err(Error::TruncatedStatusLine);
+            }
+            &rest[1..]
+        } else {
+            trim(rest)
+        };
+        if rest.len() < 3 {
+            return Err(Error::TruncatedStatusLine);
+        }
+        let (code, reason) = rest.split_at(3);
+        if !code.iter().all(u8::is_ascii_digit) {
+            return Err(Error::InvalidStatusLine);
+        }
+        let code = code.iter().fold(0, |code, &digit| code * 10 + (digit - b'0') as u16);
+
+        let reason = if self.strict {
+            // The space before the reason phrase is required, even if it's empty
+            match reason.split_first() {
+                Some((&b' ', reason)) if reason.iter().all(|&b| b == b'\t' || (b >= b' ' && b != 0x7F)) => reason,
+                _ => return Err(Error::InvalidStatusLine)
+            }
+        } else {
+            match reason.first() {
+                None | Some(&b' ') | Some(&b'\t') => trim(reason),
+                Some(_) => return Err(Error::InvalidStatusLine)
+            }
+        };
+
+        Ok((
+            String::from_utf8_lossy(version).into_owned(),
+            StatusCode::from_u16(code)?,
+            String::from_utf8_lossy(reason).into_owned()
+        ))
+    }
+}
+
+/// Strip spaces and tabs, and the line ending
+pub(crate) fn trim(mut bytes: &[u8]) -> &[u8] {
+    while let Some((&first, rest)) = bytes.split_first() {
+        if first != b' ' && first != b'\t' { break; }
+        bytes = rest;
+    }
+    while let Some((&last, rest)) = bytes.split_last() {
+        if last != b' ' && last != b'\t' && last != b'\r' && last != b'\n' { break; }
+        bytes = rest;
+    }
+    bytes
+}
 
 /// Response struct
 pub struct Response<Stream: Read> {
     pub http_version: String,
-    pub status: u16,
+    pub status: StatusCode,
     pub description: String,
     pub headers: HashMap<String, Vec<u8>>,
-    pub body: BufReader<Stream>
+    /// Timings up until the first byte of the response.
+    /// See [`timings`](#method.timings) for the complete record.
+    pub timings: Timings,
+    pub body: Body<Stream>
 }
 impl<Stream: Read> Response<Stream> {
     /// Parse a stream into a response struct
-    pub fn new(mut stream: BufReader<Stream>) -> Result<Response<Stream>, Error> {
-        let mut status = String::new();
-        stream.read_line(&mut status)?;
-        let mut parts = status.split_whitespace();
-
-        let http_version = parts.next().unwrap();
-        let status = match parts.next() {
-            Some(field) => field,
-            None => return Err(Error::InvalidStatusLine)
+    pub fn new(stream: BufReader<Stream>) -> Result<Response<Stream>, Error> {
+        Parser::default().parse(stream)
+    }
+    /// Create the response to a `method` request from an already parsed
+    /// head, with `stream` positioned at the start of the body
+    pub fn from_head(head: Head, method: &str, stream: BufReader<Stream>, timings: Timings) -> Result<Response<Stream>, Error> {
+        let (framing, ambiguous) = Framing::of_response(&head, method)?;
+        Ok(Response {
+            http_version: head.http_version,
+            status: head.status,
+            description: head.description,
+            headers: head.headers,
+            timings: timings,
+            body: Body::framed(Framed::new(stream, framing, ambiguous))
+        })
+    }
+
+    /// Look up a header, ignoring the case of its name
+    pub fn header(&self, name: &str) -> Option<&[u8]> {
+        self.headers.iter()
+            .find(|&(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, val)| &**val)
+    }
+
+    /// Every "Set-Cookie" header, in the order they were sent. They're
+    /// stored in `headers` separated by newlines.
+    pub fn set_cookies(&self) -> Vec<&[u8]> {
+        self.header("Set-Cookie").map_or(Vec::new(), split_values)
+    }
+
+    /// Transparently decode the body if every encoding in its
+    /// "Content-Encoding" is supported, producing at most `limit` bytes per
+    /// layer. The "Content-Encoding" and "Content-Length" headers are
+    /// removed since they no longer apply.
+    pub fn decompress(mut self, limit: u64) -> Self
+        where Stream: 'static
+    {
+        // Bodies that are empty by definition, like for HEAD requests or 304
+        // responses, have nothing to decode
+        match self.body.framed_ref().framing() {
+            Framing::Empty | Framing::Length(0) => return self,
+            _ => ()
+        }
+        let encodings = match self.header("Content-Encoding").and_then(Encoding::parse_list) {
+            Some(encodings) => encodings,
+            None => return self
         };
-        let status = status.parse()?;
-        let description = match parts.next() {
-            Some(field) => field,
-            None => return Err(Error::InvalidStatusLine)
+        self.headers.retain(|key, _| {
+            !key.eq_ignore_ascii_case("Content-Encoding") && !key.eq_ignore_ascii_case("Content-Length")
+        });
+        for encoding in encodings.into_iter().rev() {
+            self.body = self.body.decode(encoding, limit);
+        }
+        self
+    }
+
+    /// True if another response could be read from this connection: the
+    /// body was framed unambiguously and has been read to the end, and the
+    /// server didn't ask to close the connection.
+    pub fn is_reusable(&self) -> bool {
+        let keep_alive = match self.header("Connection") {
+            Some(val) if val.eq_ignore_ascii_case(b"close") => false,
+            Some(val) if val.eq_ignore_ascii_case(b"keep-alive") => true,
+            _ => self.http_version == "HTTP/1.1"
         };
+        keep_alive && self.body.framed_ref().is_reusable()
+    }
 
-        let mut headers = HashMap::new();
+    /// All timings, including the body download if the body has been read
+    /// to the end.
+    pub fn timings(&self) -> Timings {
+        let mut timings = self.timings;
+        timings.receive = self.body.elapsed().unwrap_or_default();
+        timings
+    }
 
-        loop {
-            let mut line = String::new();
-            stream.read_line(&mut line)?;
-            let line = line.trim();
-            if line.is_empty() {
-                break;
-            }
+    /// Returns true if self.status is 2XX, false otherwise
+    pub fn is_success(&self) -> bool { self.status.is_success() }
+}
+impl Response<HttpStream> {
+    /// Details about the TLS connection, or `None` if it isn't one. See
+    /// [`HttpStream::tls_info`](../enum.HttpStream.html#method.tls_info)
+    pub fn tls_info(&self) -> Result<Option<TlsInfo>, Error> {
+        self.body.get_ref().get_ref().tls_info()
+    }
+}
 
-            let mut parts = line.splitn(2, ':');
-            headers.insert(
-                parts.next().unwrap().trim().to_string(),
-                match parts.next() {
-                    Some(field) => field.trim().as_bytes().to_vec(),
-                    None => return Err(Error::InvalidHeader)
-                }
-            );
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn parse(parser: &Parser, raw: &[u8]) -> Result<Response<Cursor<Vec<u8>>>, Error> {
+        parser.parse(BufReader::new(Cursor::new(raw.to_vec())))
+    }
+
+    #[test]
+    fn status_line() {
+        let (strict, lenient) = (Parser::new().strict(true), Parser::new());
+        for parser in &[&strict, &lenient] {
+            let res = parse(parser, b"HTTP/1.1 404 Not Found\r\n\r\n").unwrap();
+            assert_eq!((&*res.http_version, res.status.as_u16(), &*res.description), ("HTTP/1.1", 404, "Not Found"));
+
+            let res = parse(parser, b"HTTP/1.1 204 \r\n\r\n").unwrap();
+            assert_eq!((res.status.as_u16(), &*res.description), (204, ""));
+
+            match parse(parser, b"") { Err(Error::EmptyResponse) => (), _ => panic!() }
+            match parse(parser, b"HTTP/1.1 20") { Err(Error::TruncatedStatusLine) => (), _ => panic!() }
+            match parse(parser, b"SSH-2.0-OpenSSH\r\n") { Err(Error::NotHttp) => (), _ => panic!() }
+            match parse(parser, b"HTTP/1.1 2x0 OK\r\n") { Err(Error::InvalidStatusLine) => (), _ => panic!() }
         }
 
-        Ok(Response {
-            http_version: http_version.to_string(),
-            status: status,
-            description: description.to_string(),
-            headers: headers,
-            body: stream
-        })
+        let res = parse(&lenient, b"\r\nhttp/1.0  200\n\n").unwrap();
+        assert_eq!((res.status.as_u16(), &*res.description), (200, ""));
+        assert!(parse(&strict, b"HTTP/1.0 200\r\n\r\n").is_err());
+        assert!(parse(&strict, b"HTTP/1.0 200 OK\n\n").is_err());
+        assert!(parse(&strict, b"HTTP/1.0  200 OK\r\n\r\n").is_err());
     }
+    #[test]
+    fn informational() {
+        let hints = Arc::new(::std::sync::Mutex::new(Vec::new()));
+        let seen = Arc::clone(&hints);
+        let parser = Parser::new().on_informational(move |head| {
+            seen.lock().unwrap().push((head.status.as_u16(), head.headers.get("Link").cloned()));
+        });
+        let raw = b"HTTP/1.1 100 Continue\r\n\r\n\
+                    HTTP/1.1 103 Early Hints\r\nLink: </style.css>; rel=preload\r\n\r\n\
+                    HTTP/1.1 200 OK\r\n\r\n";
+        assert_eq!(parse(&parser, raw).unwrap().status, 200);
+        assert_eq!(*hints.lock().unwrap(), vec![
+            (100, None),
+            (103, Some(b"</style.css>; rel=preload".to_vec()))
+        ]);
 
-    #[cfg(not(feature = "http"))]
-    /// Returns true if self.status is 2XX, false otherwise
-    pub fn is_success(&self) -> bool { (self.status as f32 / 100.0) as i32 == 2 }
-
-    #[cfg(feature = "http")]
-    pub fn try_into(mut self) -> io::Result<::http::Response<Vec<u8>>> {
-        let mut body = Vec::new();
-        self.body.read_to_end(&mut body)?;
-
-        let headers: Vec<(&str, &[u8])> = self.headers.iter().map(|(k, v)| (&**k, &**v)).collect();
-
-        Ok(
-            ::http::Response::builder()
-                .version(match &*self.http_version {
-                    "HTTP/2.0" => ::http::version::HTTP_2,
-                    "HTTP/0.9" => ::http::version::HTTP_09,
-                    "HTTP/1.0" => ::http::version::HTTP_10,
-                    "HTTP/1.1" => ::http::version::HTTP_11,
-                    _ => ::http::version::HTTP_10, // Default because bother printing an error
-                })
-                .status(self.status)
-                .extension(self.description)
-                .headers(headers)
-                .body(body)
-                .unwrap()
-        )
+        let res = parse(&Parser::new(), b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\r\n").unwrap();
+        assert_eq!(res.status, 101);
+    }
+    #[test]
+    fn repeated() {
+        let raw = b"HTTP/1.1 200 OK\r\nVary: Accept\r\nSet-Cookie: a=1; Expires=Wed, 21 Oct 2026 07:28:00 GMT\r\n\
+                    vary: Accept-Encoding\r\nSet-Cookie: b=2\r\n\r\n";
+        let res = parse(&Parser::new(), raw).unwrap();
+        assert_eq!(res.header("Vary"), Some(&b"Accept, Accept-Encoding"[..]));
+        assert_eq!(res.set_cookies(), vec![&b"a=1; Expires=Wed, 21 Oct 2026 07:28:00 GMT"[..], &b"b=2"[..]]);
+        assert!(parse(&Parser::new(), b"HTTP/1.1 200 OK\r\n\r\n").unwrap().set_cookies().is_empty());
+    }
+    #[test]
+    fn limits() {
+        let parser = Parser::new().limits(Limits {
+            status_line: 20,
+            header_line: 16,
+            headers: 2,
+            header_bytes: 24,
+            ..Limits::default()
+        });
+        let res = parse(&parser, b"HTTP/1.1 200 OK\r\nA: 1\r\nB: 2\r\n\r\n").unwrap();
+        assert_eq!(res.headers.len(), 2);
+
+        match parse(&parser, b"HTTP/1.1 200 Very Long Reason\r\n\r\n") { Err(Error::StatusLineTooLong) => (), _ => panic!() }
+        match parse(&parser, b"\r\n\r\n\r\nHTTP/1.1 200 OK, ok\r\n\r\n") { Err(Error::StatusLineTooLong) => (), _ => panic!() }
+        match parse(&parser, b"HTTP/1.1 200 OK\r\nX-Long: 0123456789\r\n\r\n") { Err(Error::HeaderLineTooLong) => (), _ => panic!() }
+        match parse(&parser, b"HTTP/1.1 200 OK\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n") { Err(Error::TooManyHeaders) => (), _ => panic!() }
+        match parse(&parser, b"HTTP/1.1 200 OK\r\nA: 0123456\r\nB: 0123456\r\n\r\n") { Err(Error::HeadersTooLarge) => (), _ => panic!() }
+
+        let parser = Parser::new().limits(Limits { interim_responses: 2, ..Limits::default() });
+        let raw = b"HTTP/1.1 103 Early Hints\r\n\r\nHTTP/1.1 103 Early Hints\r\n\r\nHTTP/1.1 200 OK\r\n\r\n";
+        assert_eq!(parse(&parser, raw).unwrap().status, 200);
+        let endless: Vec<u8> = b"HTTP/1.1 102 Processing\r\n\r\n".iter().cloned().cycle().take(1 << 16).collect();
+        match parse(&parser, &endless) { Err(Error::TooManyInterimResponses) => (), _ => panic!() }
+
+        // Never reads more than it has to
+        let endless = io::repeat(b'a').take(1 << 30);
+        match Parser::new().parse(BufReader::new(endless)) { Err(Error::StatusLineTooLong) => (), _ => panic!() }
     }
 }
@@ -0,0 +1,209 @@
+use date::parse_http_date;
+use error::Error;
+use response::Response;
+use std::cell::Cell;
+use std::cmp;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use {HttpStream, Method};
+
+/// When and how often a [`Client`](../client/struct.Client.html) retries
+/// failed requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one
+    pub max_attempts: u32,
+    /// The delay before the first retry. Doubles with each attempt.
+    pub base_delay: Duration,
+    /// Upper bound for any delay. A longer `Retry-After` is cut down to
+    /// this.
+    pub max_delay: Duration,
+    /// Randomize each delay between half and all of its value
+    pub jitter: bool,
+    /// Retry responses with these status codes
+    pub statuses: Vec<u16>,
+    /// Only retry methods which are safe to repeat, like GET and PUT
+    pub idempotent_only: bool,
+    /// Wait as long as the `Retry-After` header says, if there is one
+    pub retry_after: bool
+}
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+            statuses: vec![429, 502, 503, 504],
+            idempotent_only: true,
+            retry_after: true
+        }
+    }
+}
+impl RetryPolicy {
+    /// Create the default policy
+    pub fn new() -> RetryPolicy { RetryPolicy::default() }
+
+    /// Set the total number of attempts
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+    /// Set the initial delay and the maximum delay
+    pub fn delay(mut self, base_delay: Duration, max_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self.max_delay = max_delay;
+        self
+    }
+    /// Enable or disable jitter
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+    /// Set which status codes are retried
+    pub fn statuses(mut self, statuses: Vec<u16>) -> Self {
+        self.statuses = statuses;
+        self
+    }
+    /// Allow retrying non-idempotent methods, such as POST
+    pub fn idempotent_only(mut self, idempotent_only: bool) -> Self {
+        self.idempotent_only = idempotent_only;
+        self
+    }
+    /// Enable or disable honouring `Retry-After`
+    pub fn retry_after(mut self, retry_after: bool) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Returns how long to wait before retrying, or `None` if the result
+    /// should be returned. `attempt` starts at 1.
+    pub fn should_retry(
+        &self,
+        method: &Method,
+        attempt: u32,
+        result: Result<&Response<HttpStream>, &Error>
+    ) -> Option<Duration> {
+        if attempt >= self.max_attempts || (self.idempotent_only && !method.is_idempotent()) {
+            return None;
+        }
+        match result {
+            Err(&Error::IoError(_)) => Some(self.backoff(attempt)),
+            Err(_) => None,
+            Ok(res) if self.statuses.contains(&res.status.as_u16()) => {
+                match res.header("Retry-After").and_then(parse_retry_after) {
+                    Some(delay) if self.retry_after => Some(cmp::min(delay, self.max_delay)),
+                    _ => Some(self.backoff(attempt))
+                }
+            },
+            Ok(_) => None
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self.base_delay
+            .checked_mul(1 << cmp::min(attempt.saturating_sub(1), 16))
+            .map(|delay| cmp::min(delay, self.max_delay))
+            .unwrap_or(self.max_delay);
+        if !self.jitter {
+            return delay;
+        }
+        let half = delay / 2;
+        let nanos = half.as_secs() * 1_000_000_000 + half.subsec_nanos() as u64;
+        half + Duration::from_nanos(random() % (nanos + 1))
+    }
+}
+
+/// Parse a `Retry-After` value, either in seconds or as an HTTP-date
+pub fn parse_retry_after(value: &[u8]) -> Option<Duration> {
+    let value = ::std::str::from_utf8(value).ok()?.trim();
+    if let Ok(secs) = value.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = parse_http_date(value)?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+thread_local! {
+    static SEED: Cell<u64> = Cell::new(0);
+}
+/// A xorshift generator. Good enough for jitter, nothing else.
+fn random() -> u64 {
+    SEED.with(|seed| {
+        let mut x = seed.get();
+        if x == 0 {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+            x = now.as_secs() ^ (now.subsec_nanos() as u64) << 32 | 1;
+        }
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        seed.set(x);
+        x
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use server;
+    use std::io::{BufReader, Cursor};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use {consts, Client, Request};
+
+    #[test]
+    fn retries() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let hits = AtomicUsize::new(0);
+        thread::spawn(move || server::serve(listener, move |_, res| {
+            if hits.fetch_add(1, Ordering::SeqCst) < 2 {
+                res.status(503, "Service Unavailable").header("Retry-After", "0");
+            }
+        }));
+
+        let url = format!("127.0.0.1:{}/", port).parse().unwrap();
+        let client = Client::new().retry(RetryPolicy::new().delay(Duration::from_millis(1), Duration::from_secs(1)));
+        assert_eq!(client.request(&Request::new(url)).unwrap().status, 200);
+
+        let url = format!("127.0.0.1:{}/", port).parse().unwrap();
+        let req = Request::new(url).method(consts::POST);
+        let policy = RetryPolicy::new().idempotent_only(true);
+        assert_eq!(policy.should_retry(&req.method, 1, Err(&Error::IoError(::std::io::ErrorKind::ConnectionReset.into()))), None);
+
+        assert_eq!(parse_retry_after(b" 120 "), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after(b"Sun, 06 Nov 1994 08:49:37 GMT"), Some(Duration::from_secs(0)));
+        assert_eq!(parse_retry_after(b"soon"), None);
+    }
+    #[test]
+    fn delays() {
+        let policy = RetryPolicy::new()
+            .max_attempts(100)
+            .delay(Duration::from_millis(100), Duration::from_secs(1))
+            .jitter(false);
+        let reset = Error::IoError(::std::io::ErrorKind::ConnectionReset.into());
+        let delays: Vec<_> = (0..7).map(|attempt| policy.should_retry(&Method::Get, attempt, Err(&reset)).unwrap()).collect();
+        let millis = |ms: &[u64]| ms.iter().map(|&ms| Duration::from_millis(ms)).collect::<Vec<_>>();
+        assert_eq!(delays, millis(&[100, 100, 200, 400, 800, 1000, 1000]));
+        assert_eq!(policy.should_retry(&Method::Get, 99, Err(&reset)), Some(Duration::from_secs(1)));
+        assert_eq!(policy.should_retry(&Method::Get, 100, Err(&reset)), None);
+
+        // Jitter stays between half and all of the delay
+        let jittered = policy.clone().jitter(true);
+        for attempt in 1..20 {
+            let full = policy.should_retry(&Method::Get, attempt, Err(&reset)).unwrap();
+            let delay = jittered.should_retry(&Method::Get, attempt, Err(&reset)).unwrap();
+            assert!(delay >= full / 2 && delay <= full, "{:?} for {:?}", delay, full);
+        }
+
+        // Retry-After wins over the backoff, but is held to the maximum delay
+        let response = |retry_after: &str| {
+            let raw = format!("HTTP/1.1 503 Service Unavailable\r\nRetry-After: {}\r\n\r\n", retry_after);
+            Response::new(BufReader::new(HttpStream::Memory(Cursor::new(raw.into_bytes())))).unwrap()
+        };
+        assert_eq!(policy.should_retry(&Method::Get, 1, Ok(&response("0"))), Some(Duration::from_secs(0)));
+        assert_eq!(policy.should_retry(&Method::Get, 1, Ok(&response("3600"))), Some(Duration::from_secs(1)));
+        assert_eq!(policy.clone().retry_after(false).should_retry(&Method::Get, 3, Ok(&response("3600"))), Some(Duration::from_millis(400)));
+    }
+}
@@ -0,0 +1,390 @@
+use error::Error;
+use framing::parse_content_length;
+use response::{read_headers, read_line, Limits};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use {is_field_value, is_token};
+
+/// A request received by the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub http_version: String,
+    pub headers: HashMap<String, Vec<u8>>,
+    pub body: Vec<u8>
+}
+impl Request {
+    /// Parse a request from a stream. Returns `Ok(None)` if the connection
+    /// was closed before a new request started.
+    pub fn new<R: BufRead>(stream: &mut R) -> Result<Option<Request>, Error> {
+        Request::with_limits(stream, &Limits::default())
+    }
+    /// Same as [`new`](#method.new), but with custom limits. The request
+    /// line is held to `limits.status_line`. Bodies are only accepted with
+    /// "Content-Length", since "Transfer-Encoding" is rejected with
+    /// `Error::InvalidTransferEncoding`.
+    pub fn with_limits<R: BufRead>(stream: &mut R, limits: &Limits) -> Result<Option<Request>, Error> {
+        let mut line = Vec::new();
+        let len = read_line(stream, &mut line, limits.status_line)?;
+        if len == 0 {
+            return Ok(None);
+        }
+        if len > limits.status_line {
+            return Err(Error::RequestLineTooLong);
+        }
+        let line = String::from_utf8(line).map_err(|_| Error::InvalidRequestLine)?;
+        let mut parts = line.split_whitespace();
+
+        let (method, path, http_version) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(method), Some(path), Some(version)) => (method, path, version),
+            _ => return Err(Error::InvalidRequestLine)
+        };
+        if parts.next().is_some() || !http_version.starts_with("HTTP/") {
+            return Err(Error::InvalidRequestLine);
+        }
+
+        let headers = read_headers(stream, limits)?;
+
+        // Chunked request bodies aren't supported, and guessing where they
+        // end would let the next request be smuggled inside this one
+        if header(&headers, "Transfer-Encoding").is_some() {
+            return Err(Error::InvalidTransferEncoding);
+        }
+        let mut body = Vec::new();
+        if let Some(len) = header(&headers, "Content-Length") {
+            let len = parse_content_length(len)?;
+            if len > limits.request_body {
+                return Err(Error::BodyTooLarge);
+            }
+            stream.take(len).read_to_end(&mut body)?;
+            if (body.len() as u64) < len {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed before the end of the body").into());
+            }
+        }
+
+        Ok(Some(Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            http_version: http_version.to_string(),
+            headers: headers,
+            body: body
+        }))
+    }
+
+    /// Returns true if the connection should be kept open after this request.
+    /// HTTP/1.1 defaults to keep-alive, HTTP/1.0 to close.
+    pub fn keep_alive(&self) -> bool {
+        match header(&self.headers, "Connection") {
+            Some(val) if val.eq_ignore_ascii_case(b"close") => false,
+            Some(val) if val.eq_ignore_ascii_case(b"keep-alive") => true,
+            _ => self.http_version == "HTTP/1.1"
+        }
+    }
+}
+
+fn header<'a>(headers: &'a HashMap<String, Vec<u8>>, name: &str) -> Option<&'a [u8]> {
+    headers.iter()
+        .find(|&(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, val)| &**val)
+}
+
+/// Builds the response to a request. The body is buffered so the
+/// "Content-Length" header can be set before anything is sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseWriter {
+    pub status: u16,
+    pub description: String,
+    pub headers: HashMap<String, Vec<u8>>,
+    pub body: Vec<u8>
+}
+impl Default for ResponseWriter {
+    fn default() -> Self {
+        ResponseWriter {
+            status: 200,
+            description: "OK".to_string(),
+            headers: HashMap::new(),
+            body: Vec::new()
+        }
+    }
+}
+impl ResponseWriter {
+    /// Set the status code and description
+    pub fn status<S: Into<String>>(&mut self, status: u16, description: S) -> &mut Self {
+        self.status = status;
+        self.description = description.into();
+        self
+    }
+    /// Set a header. "Content-Length" and "Connection" are added
+    /// automatically.
+    pub fn header<S: Into<String>, B: Into<Vec<u8>>>(&mut self, key: S, val: B) -> &mut Self {
+        self.headers.insert(key.into(), val.into());
+        self
+    }
+
+    /// Serialize the response to `stream`, assuming it's the response to a
+    /// GET request. See [`send_for`](#method.send_for)
+    pub fn send<W: Write>(&self, stream: &mut W, keep_alive: bool) -> io::Result<()> {
+        self.send_for(stream, "GET", keep_alive)
+    }
+    /// Serialize the response to a `method` request to `stream`. Responses
+    /// to HEAD and 304 responses only get the "Content-Length" of the body,
+    /// and 1xx and 204 responses get neither (RFC 9110 section 8.6).
+    /// Nothing is written if the description or a header would break the
+    /// line it's on, which is an `InvalidInput` error.
+    pub fn send_for<W: Write>(&self, stream: &mut W, method: &str, keep_alive: bool) -> io::Result<()> {
+        if !self.is_valid() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid status description or header"));
+        }
+        let has_length = self.status >= 200 && self.status != 204;
+        let has_body = has_length && self.status != 304 && method != "HEAD";
+
+        let mut out = Vec::new();
+        write!(out, "HTTP/1.1 {} {}\r\n", self.status, self.description)?;
+        for (name, value) in &self.headers {
+            if name.eq_ignore_ascii_case("Content-Length") || name.eq_ignore_ascii_case("Connection") {
+                continue;
+            }
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(b": ");
+            out.extend_from_slice(value);
+            out.extend_from_slice(b"\r\n");
+        }
+        if has_length {
+            write!(out, "Content-Length: {}\r\n", self.body.len())?;
+        }
+        write!(out, "Connection: {}\r\n\r\n", if keep_alive { "keep-alive" } else { "close" })?;
+        if has_body {
+            out.extend_from_slice(&self.body);
+        }
+
+        stream.write_all(&out)?;
+        stream.flush()
+    }
+    /// True if the response can be written without injecting anything,
+    /// following the same rules as [`validate_request`](../fn.validate_request.html)
+    fn is_valid(&self) -> bool {
+        is_field_value(self.description.as_bytes())
+            && self.headers.iter().all(|(name, value)| is_token(name) && is_field_value(value))
+    }
+}
+impl Write for ResponseWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> { self.body.write(buf) }
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+/// The response sent for a request that couldn't be parsed, if the
+/// connection is still worth answering
+fn error_status(error: &Error) -> Option<(u16, &'static str)> {
+    match *error {
+        Error::IoError(_) => None,
+        Error::InvalidTransferEncoding => Some((501, "Not Implemented")),
+        Error::BodyTooLarge => Some((413, "Payload Too Large")),
+        Error::RequestLineTooLong => Some((414, "URI Too Long")),
+        Error::HeaderLineTooLong |
+        Error::HeadersTooLarge |
+        Error::TooManyHeaders => Some((431, "Request Header Fields Too Large")),
+        _ => Some((400, "Bad Request"))
+    }
+}
+
+/// Serve requests on a single connection until either side closes it.
+pub fn handle_connection<F>(stream: TcpStream, handler: &F) -> Result<(), Error>
+    where F: Fn(&Request, &mut ResponseWriter)
+{
+    handle_connection_with(stream, &Limits::default(), handler)
+}
+/// Same as [`handle_connection`](fn.handle_connection.html), but requests
+/// are parsed with custom limits. An invalid request gets an error
+/// response and closes the connection, since it's unknown where the next
+/// one would start. The connection is also closed once it's been idle for
+/// `limits.idle_timeout`. If the handler sets a header that can't be
+/// sent, the client gets `500 Internal Server Error` instead.
+pub fn handle_connection_with<F>(stream: TcpStream, limits: &Limits, handler: &F) -> Result<(), Error>
+    where F: Fn(&Request, &mut ResponseWriter)
+{
+    stream.set_read_timeout(limits.idle_timeout)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    loop {
+        let req = match Request::with_limits(&mut reader, limits) {
+            Ok(Some(req)) => req,
+            Ok(None) => break,
+            Err(Error::IoError(ref err)) if err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut => break,
+            Err(err) => {
+                if let Some((status, description)) = error_status(&err) {
+                    let mut res = ResponseWriter::default();
+                    res.status(status, description);
+                    let _ = res.send(&mut writer, false);
+                }
+                return Err(err);
+            }
+        };
+        let mut res = ResponseWriter::default();
+        handler(&req, &mut res);
+        if !res.is_valid() {
+            res = ResponseWriter::default();
+            res.status(500, "Internal Server Error");
+        }
+
+        let keep_alive = req.keep_alive();
+        res.send_for(&mut writer, &req.method, keep_alive)?;
+        if !keep_alive {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Accept connections forever, spawning one thread per connection.
+pub fn serve<F>(listener: TcpListener, handler: F) -> io::Result<()>
+    where F: Fn(&Request, &mut ResponseWriter) + Send + Sync + 'static
+{
+    serve_with(listener, Limits::default(), handler)
+}
+/// Same as [`serve`](fn.serve.html), but requests are parsed with custom
+/// limits, which also set how long idle connections are kept open
+pub fn serve_with<F>(listener: TcpListener, limits: Limits, handler: F) -> io::Result<()>
+    where F: Fn(&Request, &mut ResponseWriter) + Send + Sync + 'static
+{
+    let handler = Arc::new(handler);
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let handler = Arc::clone(&handler);
+        thread::spawn(move || handle_connection_with(stream, &limits, &*handler));
+    }
+    Ok(())
+}
+
+/// Accept connections forever, handing them to a fixed pool of `threads`
+/// worker threads.
+pub fn serve_pool<F>(listener: TcpListener, threads: usize, handler: F) -> io::Result<()>
+    where F: Fn(&Request, &mut ResponseWriter) + Send + Sync + 'static
+{
+    serve_pool_with(listener, threads, Limits::default(), handler)
+}
+/// Same as [`serve_pool`](fn.serve_pool.html), but requests are parsed with
+/// custom limits. Each idle connection holds on to a worker until
+/// `limits.idle_timeout` passes.
+pub fn serve_pool_with<F>(listener: TcpListener, threads: usize, limits: Limits, handler: F) -> io::Result<()>
+    where F: Fn(&Request, &mut ResponseWriter) + Send + Sync + 'static
+{
+    let handler = Arc::new(handler);
+    let (tx, rx) = mpsc::channel::<TcpStream>();
+    let rx = Arc::new(Mutex::new(rx));
+
+    for _ in 0..threads.max(1) {
+        let handler = Arc::clone(&handler);
+        let rx = Arc::clone(&rx);
+        thread::spawn(move || loop {
+            let stream = match rx.lock().unwrap().recv() {
+                Ok(stream) => stream,
+                Err(_) => break
+            };
+            let _ = handle_connection_with(stream, &limits, &*handler);
+        });
+    }
+
+    for stream in listener.incoming() {
+        if tx.send(stream?).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn parse() {
+        let mut input = &b"POST /metrics HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nhello"[..];
+        let req = Request::new(&mut input).unwrap().unwrap();
+        assert_eq!(req.method, "POST");
+        assert_eq!(req.path, "/metrics");
+        assert_eq!(req.body, b"hello");
+        assert!(req.keep_alive());
+
+        let mut input = &b""[..];
+        assert!(Request::new(&mut input).unwrap().is_none());
+
+        let mut input = &b"GET /\r\n\r\n"[..];
+        assert!(Request::new(&mut input).is_err());
+    }
+    #[test]
+    fn framing() {
+        let mut input = &b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n"[..];
+        match Request::new(&mut input) { Err(Error::InvalidTransferEncoding) => (), _ => panic!() }
+
+        let mut input = &b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nhello"[..];
+        match Request::new(&mut input) { Err(Error::IoError(ref err)) if err.kind() == io::ErrorKind::UnexpectedEof => (), _ => panic!() }
+
+        // Identical lengths on separate lines are the same length
+        let mut input = &b"POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 5\r\n\r\nhello"[..];
+        assert_eq!(Request::new(&mut input).unwrap().unwrap().body, b"hello");
+
+        let mut input = &b"POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n"[..];
+        match Request::new(&mut input) { Err(Error::InvalidContentLength) => (), _ => panic!() }
+
+        let limits = Limits { status_line: 20, request_body: 4, ..Limits::default() };
+        let mut input = &b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello"[..];
+        match Request::with_limits(&mut input, &limits) { Err(Error::BodyTooLarge) => (), _ => panic!() }
+        let mut input = &b"GET /long/path HTTP/1.1\r\n\r\n"[..];
+        match Request::with_limits(&mut input, &limits) { Err(Error::RequestLineTooLong) => (), _ => panic!() }
+    }
+    #[test]
+    fn send() {
+        let mut res = ResponseWriter::default();
+        res.status(404, "Not Found").write_all(b"nope").unwrap();
+
+        let mut out = Vec::new();
+        res.send(&mut out, false).unwrap();
+        assert_eq!(
+            out,
+            &b"HTTP/1.1 404 Not Found\r\nContent-Length: 4\r\nConnection: close\r\n\r\nnope"[..]
+        );
+
+        // No body where there can't be one
+        let mut out = Vec::new();
+        res.send_for(&mut out, "HEAD", true).unwrap();
+        assert_eq!(out, &b"HTTP/1.1 404 Not Found\r\nContent-Length: 4\r\nConnection: keep-alive\r\n\r\n"[..]);
+        let mut out = Vec::new();
+        res.status(304, "Not Modified").send(&mut out, true).unwrap();
+        assert_eq!(out, &b"HTTP/1.1 304 Not Modified\r\nContent-Length: 4\r\nConnection: keep-alive\r\n\r\n"[..]);
+        let mut out = Vec::new();
+        res.status(204, "No Content").send(&mut out, true).unwrap();
+        assert_eq!(out, &b"HTTP/1.1 204 No Content\r\nConnection: keep-alive\r\n\r\n"[..]);
+
+        for &(name, value) in &[("X-A", &b"1\r\nSet-Cookie: a=1"[..]), ("X-A\r\nX-B", &b"1"[..]), ("", &b"1"[..])] {
+            let mut res = ResponseWriter::default();
+            res.header(name, value);
+            let mut out = Vec::new();
+            match res.send(&mut out, false) { Err(ref err) if err.kind() == io::ErrorKind::InvalidInput => (), _ => panic!() }
+            assert!(out.is_empty());
+        }
+        let mut out = Vec::new();
+        assert!(ResponseWriter::default().status(200, "OK\r\nX-A: 1").send(&mut out, false).is_err());
+    }
+    #[test]
+    fn idle_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let limits = Limits { idle_timeout: Some(Duration::from_millis(100)), ..Limits::default() };
+        thread::spawn(move || serve_pool_with(listener, 1, limits, |_, res| {
+            res.write_all(b"hi").unwrap();
+        }));
+
+        // Holds the only worker until it times out
+        let mut idle = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let mut body = String::new();
+        ::get(format!("127.0.0.1:{}/", port).parse().unwrap()).unwrap().body.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "hi");
+        assert_eq!(idle.read(&mut [0; 1]).unwrap(), 0);
+    }
+}
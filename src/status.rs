@@ -0,0 +1,137 @@
+use error::Error;
+use std::fmt;
+
+/// A response status code, between 100 and 999
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StatusCode(u16);
+
+impl StatusCode {
+    pub const OK: StatusCode = StatusCode(200);
+    pub const NO_CONTENT: StatusCode = StatusCode(204);
+    pub const NOT_MODIFIED: StatusCode = StatusCode(304);
+    pub const BAD_REQUEST: StatusCode = StatusCode(400);
+    pub const NOT_FOUND: StatusCode = StatusCode(404);
+    pub const TOO_MANY_REQUESTS: StatusCode = StatusCode(429);
+    pub const INTERNAL_SERVER_ERROR: StatusCode = StatusCode(500);
+    pub const SERVICE_UNAVAILABLE: StatusCode = StatusCode(503);
+
+    /// Returns `Error::InvalidStatusCode` unless `code` has three digits
+    pub fn from_u16(code: u16) -> Result<StatusCode, Error> {
+        match code {
+            100..=999 => Ok(StatusCode(code)),
+            _ => Err(Error::InvalidStatusCode)
+        }
+    }
+    pub fn as_u16(&self) -> u16 { self.0 }
+
+    /// The standard reason phrase for this code, if it has one
+    pub fn reason(&self) -> Option<&'static str> {
+        Some(match self.0 {
+            100 => "Continue",
+            101 => "Switching Protocols",
+            102 => "Processing",
+            103 => "Early Hints",
+            200 => "OK",
+            201 => "Created",
+            202 => "Accepted",
+            203 => "Non-Authoritative Information",
+            204 => "No Content",
+            205 => "Reset Content",
+            206 => "Partial Content",
+            207 => "Multi-Status",
+            208 => "Already Reported",
+            226 => "IM Used",
+            300 => "Multiple Choices",
+            301 => "Moved Permanently",
+            302 => "Found",
+            303 => "See Other",
+            304 => "Not Modified",
+            305 => "Use Proxy",
+            307 => "Temporary Redirect",
+            308 => "Permanent Redirect",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            402 => "Payment Required",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            406 => "Not Acceptable",
+            407 => "Proxy Authentication Required",
+            408 => "Request Timeout",
+            409 => "Conflict",
+            410 => "Gone",
+            411 => "Length Required",
+            412 => "Precondition Failed",
+            413 => "Content Too Large",
+            414 => "URI Too Long",
+            415 => "Unsupported Media Type",
+            416 => "Range Not Satisfiable",
+            417 => "Expectation Failed",
+            418 => "I'm a teapot",
+            421 => "Misdirected Request",
+            422 => "Unprocessable Content",
+            423 => "Locked",
+            424 => "Failed Dependency",
+            425 => "Too Early",
+            426 => "Upgrade Required",
+            428 => "Precondition Required",
+            429 => "Too Many Requests",
+            431 => "Request Header Fields Too Large",
+            451 => "Unavailable For Legal Reasons",
+            500 => "Internal Server Error",
+            501 => "Not Implemented",
+            502 => "Bad Gateway",
+            503 => "Service Unavailable",
+            504 => "Gateway Timeout",
+            505 => "HTTP Version Not Supported",
+            506 => "Variant Also Negotiates",
+            507 => "Insufficient Storage",
+            508 => "Loop Detected",
+            510 => "Not Extended",
+            511 => "Network Authentication Required",
+            _ => return None
+        })
+    }
+
+    /// 1XX
+    pub fn is_informational(&self) -> bool { self.0 / 100 == 1 }
+    /// 2XX
+    pub fn is_success(&self) -> bool { self.0 / 100 == 2 }
+    /// 3XX
+    pub fn is_redirection(&self) -> bool { self.0 / 100 == 3 }
+    /// 4XX
+    pub fn is_client_error(&self) -> bool { self.0 / 100 == 4 }
+    /// 5XX
+    pub fn is_server_error(&self) -> bool { self.0 / 100 == 5 }
+}
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{}", self.0) }
+}
+impl PartialEq<u16> for StatusCode {
+    fn eq(&self, other: &u16) -> bool { self.0 == *other }
+}
+impl PartialEq<StatusCode> for u16 {
+    fn eq(&self, other: &StatusCode) -> bool { *self == other.0 }
+}
+impl From<StatusCode> for u16 {
+    fn from(status: StatusCode) -> u16 { status.0 }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn classes() {
+        let status = StatusCode::from_u16(404).unwrap();
+        assert_eq!(status, 404);
+        assert_eq!(status.reason(), Some("Not Found"));
+        assert!(status.is_client_error() && !status.is_success());
+        assert!(StatusCode::from_u16(103).unwrap().is_informational());
+        assert!(StatusCode::from_u16(308).unwrap().is_redirection());
+        assert!(StatusCode::from_u16(599).unwrap().is_server_error());
+        assert_eq!(StatusCode::from_u16(599).unwrap().reason(), None);
+        assert!(StatusCode::from_u16(99).is_err());
+        assert!(StatusCode::from_u16(1000).is_err());
+    }
+}
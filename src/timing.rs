@@ -0,0 +1,25 @@
+use std::time::Duration;
+
+/// How long each phase of a request took.
+/// Phases that didn't happen (such as TLS for plain HTTP) are `None`/zero.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Timings {
+    /// Resolving the host name
+    pub dns: Duration,
+    /// Opening the TCP connection
+    pub connect: Duration,
+    /// The TLS handshake, if any
+    pub tls: Option<Duration>,
+    /// Writing the request
+    pub send: Duration,
+    /// Waiting for the first byte of the response
+    pub wait: Duration,
+    /// Reading the rest of the response
+    pub receive: Duration
+}
+impl Timings {
+    /// The sum of all phases
+    pub fn total(&self) -> Duration {
+        self.dns + self.connect + self.tls.unwrap_or_default() + self.send + self.wait + self.receive
+    }
+}
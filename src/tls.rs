@@ -0,0 +1,643 @@
+#[cfg(feature = "native-tls")]
+use native_tls;
+#[cfg(feature = "rustls")]
+use rustls;
+#[cfg(feature = "rustls")]
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+#[cfg(feature = "rustls")]
+use rustls::pki_types::pem::PemObject;
+#[cfg(feature = "rustls")]
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+#[cfg(feature = "rustls")]
+use std::convert::TryFrom;
+use error::Error;
+use std::error::Error as StdError;
+use std::fmt;
+#[cfg(feature = "rustls")]
+use std::io;
+use sha256::sha256;
+use std::net::TcpStream;
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+#[cfg(feature = "rustls")]
+use webpki_roots;
+use x509;
+use HttpStream;
+
+/// A root certificate to trust
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Certificate {
+    /// A single PEM encoded certificate
+    Pem(Vec<u8>),
+    /// A single DER encoded certificate
+    Der(Vec<u8>)
+}
+
+/// A client certificate and private key to present to the server, for
+/// mutual TLS
+#[derive(Clone, PartialEq, Eq)]
+pub enum Identity {
+    /// A DER encoded PKCS #12 archive and its password. Only supported by
+    /// native-tls.
+    Pkcs12 { der: Vec<u8>, password: String },
+    /// A PEM encoded certificate chain and PKCS #8 private key
+    Pem { certs: Vec<u8>, key: Vec<u8> }
+}
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Don't leak the key or password into logs
+        match *self {
+            Identity::Pkcs12 { .. } => f.write_str("Identity::Pkcs12 { .. }"),
+            Identity::Pem { .. } => f.write_str("Identity::Pem { .. }")
+        }
+    }
+}
+
+/// The library that performs TLS. Each one needs the cargo feature of the
+/// same name, and connecting with one that isn't enabled fails with
+/// `TlsError::Unsupported`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// The platform's TLS library, through native-tls
+    NativeTls,
+    /// rustls, which doesn't need any system libraries
+    Rustls
+}
+impl Default for Backend {
+    /// native-tls if it's enabled, otherwise rustls
+    fn default() -> Backend {
+        if cfg!(feature = "native-tls") { Backend::NativeTls } else { Backend::Rustls }
+    }
+}
+
+/// An error from the TLS library
+#[derive(Debug)]
+pub enum TlsError {
+    #[cfg(feature = "native-tls")]
+    NativeTls(native_tls::Error),
+    #[cfg(feature = "rustls")]
+    Rustls(rustls::Error),
+    /// A PEM certificate or key couldn't be read
+    #[cfg(feature = "rustls")]
+    Pem(rustls::pki_types::pem::Error),
+    /// The host isn't a valid DNS name or IP address
+    InvalidServerName,
+    /// A certificate from the server couldn't be parsed
+    InvalidCertificate,
+    /// The backend doesn't support part of the configuration
+    Unsupported(&'static str)
+}
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            #[cfg(feature = "native-tls")]
+            TlsError::NativeTls(ref inner) => write!(f, "{}", inner),
+            #[cfg(feature = "rustls")]
+            TlsError::Rustls(ref inner) => write!(f, "{}", inner),
+            #[cfg(feature = "rustls")]
+            TlsError::Pem(ref inner) => write!(f, "{}", inner),
+            TlsError::InvalidServerName |
+            TlsError::InvalidCertificate => write!(f, "{}", self.description()),
+            TlsError::Unsupported(what) => write!(f, "{}: {}", self.description(), what)
+        }
+    }
+}
+impl StdError for TlsError {
+    fn description(&self) -> &str {
+        match *self {
+            #[cfg(feature = "native-tls")]
+            TlsError::NativeTls(ref inner) => inner.description(),
+            #[cfg(feature = "rustls")]
+            TlsError::Rustls(_) => "TLS error",
+            #[cfg(feature = "rustls")]
+            TlsError::Pem(_) => "TLS error: Invalid PEM",
+            TlsError::InvalidServerName => "TLS error: Invalid server name",
+            TlsError::InvalidCertificate => "TLS error: Invalid peer certificate",
+            TlsError::Unsupported(_) => "TLS error: Not supported by this backend"
+        }
+    }
+    fn cause(&self) -> Option<&StdError> {
+        match *self {
+            #[cfg(feature = "native-tls")]
+            TlsError::NativeTls(ref inner) => Some(inner),
+            #[cfg(feature = "rustls")]
+            TlsError::Rustls(ref inner) => Some(inner),
+            #[cfg(feature = "rustls")]
+            TlsError::Pem(ref inner) => Some(inner),
+            _ => None
+        }
+    }
+}
+
+/// A certificate the server sent during the handshake
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerCertificate {
+    /// The DER encoded certificate
+    pub der: Vec<u8>,
+    /// The subject's distinguished name, like `C=SE, O=Example, CN=example.com`
+    pub subject: String,
+    pub issuer: String,
+    pub not_before: SystemTime,
+    pub not_after: SystemTime,
+    /// SHA-256 of the whole certificate
+    pub fingerprint: [u8; 32],
+    /// SHA-256 of the SubjectPublicKeyInfo, as used by
+    /// [`pin_sha256`](struct.TlsConfig.html#method.pin_sha256)
+    pub spki_sha256: [u8; 32]
+}
+impl PeerCertificate {
+    /// Parse a DER encoded X.509 certificate
+    pub fn from_der(der: Vec<u8>) -> Result<PeerCertificate, Error> {
+        let (subject, issuer, not_before, not_after, spki_sha256) = {
+            let cert = x509::parse(&der).ok_or(TlsError::InvalidCertificate)?;
+            (cert.subject, cert.issuer, cert.not_before, cert.not_after, sha256(cert.spki))
+        };
+        Ok(PeerCertificate {
+            fingerprint: sha256(&der),
+            der: der,
+            subject: subject,
+            issuer: issuer,
+            not_before: not_before,
+            not_after: not_after,
+            spki_sha256: spki_sha256
+        })
+    }
+    /// Returns true if `time` is within the certificate's validity period
+    pub fn is_valid_at(&self, time: SystemTime) -> bool {
+        self.not_before <= time && time <= self.not_after
+    }
+}
+
+/// Details about an established TLS connection
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsInfo {
+    /// The server's certificate followed by the rest of the chain it sent.
+    /// native-tls only exposes the server's own certificate.
+    pub peer_certificates: Vec<PeerCertificate>,
+    /// The protocol the server picked with ALPN, such as `http/1.1`. See
+    /// [`alpn_protocols`](struct.TlsConfig.html#method.alpn_protocols)
+    pub alpn_protocol: Option<String>,
+    /// Whether an earlier session was resumed instead of doing a full
+    /// handshake. Always false with native-tls, which doesn't say.
+    pub resumed: bool
+}
+impl TlsInfo {
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    /// Parse the DER encoded certificates from the handshake
+    pub(crate) fn new(certs: Vec<Vec<u8>>, alpn_protocol: Option<String>, resumed: bool) -> Result<TlsInfo, Error> {
+        Ok(TlsInfo {
+            peer_certificates: certs.into_iter().map(PeerCertificate::from_der).collect::<Result<_, _>>()?,
+            alpn_protocol: alpn_protocol,
+            resumed: resumed
+        })
+    }
+}
+
+/// How TLS connections are verified and authenticated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsConfig {
+    pub backend: Backend,
+    /// Trusted in addition to the built-in roots
+    pub root_certificates: Vec<Certificate>,
+    /// Trust the built-in root certificates: the system's with native-tls,
+    /// or Mozilla's with rustls. Defaults to true.
+    pub built_in_roots: bool,
+    pub identity: Option<Identity>,
+    /// See [`danger_accept_invalid_certs`](#method.danger_accept_invalid_certs)
+    pub danger_accept_invalid_certs: bool,
+    /// See [`pin_sha256`](#method.pin_sha256)
+    pub pins: Vec<[u8; 32]>,
+    /// See [`alpn_protocols`](#method.alpn_protocols)
+    pub alpn_protocols: Vec<String>
+}
+impl Default for TlsConfig {
+    fn default() -> TlsConfig {
+        TlsConfig {
+            backend: Backend::default(),
+            root_certificates: Vec::new(),
+            built_in_roots: true,
+            identity: None,
+            danger_accept_invalid_certs: false,
+            pins: Vec::new(),
+            alpn_protocols: vec![String::from("http/1.1")]
+        }
+    }
+}
+impl TlsConfig {
+    /// Create a config that verifies servers against the built-in roots
+    pub fn new() -> TlsConfig { TlsConfig::default() }
+
+    /// Set the library that performs TLS, if more than one is enabled
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+    /// Trust `cert`, such as a private CA
+    pub fn root_certificate(mut self, cert: Certificate) -> Self {
+        self.root_certificates.push(cert);
+        self
+    }
+    /// Set whether to trust the built-in root certificates. Turn this off
+    /// to only trust the ones added with
+    /// [`root_certificate`](#method.root_certificate).
+    pub fn built_in_roots(mut self, enabled: bool) -> Self {
+        self.built_in_roots = enabled;
+        self
+    }
+    /// Present `identity` if the server asks for a client certificate
+    pub fn identity(mut self, identity: Identity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+    /// Accept any certificate, including expired, self-signed and ones for
+    /// other hosts. This makes TLS useless against an active attacker, so
+    /// only use it for local testing.
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.danger_accept_invalid_certs = accept;
+        self
+    }
+    /// Only connect if the server sent a certificate whose public key has
+    /// this SHA-256 hash (of the DER encoded SubjectPublicKeyInfo, as in
+    /// `openssl x509 -pubkey | openssl pkey -pubin -outform der | openssl
+    /// dgst -sha256`). Can be called several times, such as to add a backup
+    /// key. The check happens after normal verification, which still
+    /// applies. native-tls only exposes the server's own certificate, so
+    /// pin that one rather than a CA.
+    pub fn pin_sha256(mut self, hash: [u8; 32]) -> Self {
+        self.pins.push(hash);
+        self
+    }
+    /// Offer these protocols to the server with ALPN, most preferred
+    /// first. Defaults to just `http/1.1`, and an empty list turns ALPN
+    /// off. Requests fail with `Error::UnsupportedProtocol` if the server
+    /// picks anything but HTTP/1.1, while
+    /// [`diy_request_tls`](../fn.diy_request_tls.html) hands back the
+    /// connection unused so it can speak whatever was negotiated.
+    pub fn alpn_protocols(mut self, protocols: &[&str]) -> Self {
+        self.alpn_protocols = protocols.iter().map(|protocol| protocol.to_string()).collect();
+        self
+    }
+
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    /// Build a connector from this config
+    pub(crate) fn connector(&self) -> Result<Connector, Error> {
+        match self.backend {
+            #[cfg(feature = "native-tls")]
+            Backend::NativeTls => self.native_tls().map(Connector::NativeTls),
+            #[cfg(not(feature = "native-tls"))]
+            Backend::NativeTls => Err(TlsError::Unsupported("native-tls feature disabled").into()),
+            #[cfg(feature = "rustls")]
+            Backend::Rustls => self.rustls().map(|config| Connector::Rustls(Arc::new(config))),
+            #[cfg(not(feature = "rustls"))]
+            Backend::Rustls => Err(TlsError::Unsupported("rustls feature disabled").into())
+        }
+    }
+    #[cfg(feature = "native-tls")]
+    fn native_tls(&self) -> Result<native_tls::TlsConnector, Error> {
+        let mut builder = native_tls::TlsConnector::builder();
+        for cert in &self.root_certificates {
+            builder.add_root_certificate(match *cert {
+                Certificate::Pem(ref pem) => native_tls::Certificate::from_pem(pem)?,
+                Certificate::Der(ref der) => native_tls::Certificate::from_der(der)?
+            });
+        }
+        if let Some(ref identity) = self.identity {
+            builder.identity(match *identity {
+                Identity::Pkcs12 { ref der, ref password } => native_tls::Identity::from_pkcs12(der, password)?,
+                Identity::Pem { ref certs, ref key } => native_tls::Identity::from_pkcs8(certs, key)?
+            });
+        }
+        let protocols: Vec<&str> = self.alpn_protocols.iter().map(|protocol| &**protocol).collect();
+        builder.request_alpns(&protocols);
+        builder.disable_built_in_roots(!self.built_in_roots);
+        builder.danger_accept_invalid_certs(self.danger_accept_invalid_certs);
+        Ok(builder.build()?)
+    }
+    #[cfg(feature = "rustls")]
+    fn rustls(&self) -> Result<rustls::ClientConfig, Error> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = rustls::ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+
+        let builder = if self.danger_accept_invalid_certs {
+            builder.dangerous().with_custom_certificate_verifier(Arc::new(AcceptAll(provider)))
+        } else {
+            let mut roots = rustls::RootCertStore::empty();
+            if self.built_in_roots {
+                roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+            }
+            for cert in &self.root_certificates {
+                roots.add(match *cert {
+                    Certificate::Pem(ref pem) => CertificateDer::from_pem_slice(pem)?,
+                    Certificate::Der(ref der) => CertificateDer::from(der.clone())
+                })?;
+            }
+            builder.with_root_certificates(roots)
+        };
+
+        let mut config = match self.identity {
+            None => builder.with_no_client_auth(),
+            Some(Identity::Pem { ref certs, ref key }) => {
+                let certs = CertificateDer::pem_slice_iter(certs).collect::<Result<Vec<_>, _>>()?;
+                builder.with_client_auth_cert(certs, PrivateKeyDer::from_pem_slice(key)?)?
+            },
+            Some(Identity::Pkcs12 { .. }) => return Err(TlsError::Unsupported("PKCS #12 identities need native-tls").into())
+        };
+        // Sessions are cached in the config by default, so they resume
+        // whenever Connectors hands out the same one
+        config.alpn_protocols = self.alpn_protocols.iter().map(|protocol| protocol.as_bytes().to_vec()).collect();
+        Ok(config)
+    }
+
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    /// Perform the handshake for `host` over `stream`, with this config's
+    /// connector from `connectors`
+    pub(crate) fn connect(&self, connectors: &Connectors, host: &str, stream: TcpStream) -> Result<HttpStream, Error> {
+        let stream = connectors.get(self)?.connect(host, stream)?;
+        self.check_pins(&stream)?;
+        Ok(stream)
+    }
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    /// Fail with `Error::PinMismatch` if pins are configured and none of
+    /// them match a certificate on `stream`
+    fn check_pins(&self, stream: &HttpStream) -> Result<(), Error> {
+        if self.pins.is_empty() {
+            return Ok(());
+        }
+        // A certificate that can't be parsed can't match, but shouldn't
+        // stop the others from being checked
+        let certs = stream.peer_certificates()?;
+        if certs.iter().filter_map(|der| x509::parse(der)).any(|cert| self.pins.contains(&sha256(cert.spki))) {
+            Ok(())
+        } else {
+            Err(Error::PinMismatch)
+        }
+    }
+    #[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+    pub(crate) fn connect(&self, _connectors: &Connectors, _host: &str, _stream: TcpStream) -> Result<HttpStream, Error> {
+        Err(TlsError::Unsupported("no TLS feature enabled").into())
+    }
+}
+
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+/// A client built from a [`TlsConfig`](struct.TlsConfig.html)
+pub(crate) enum Connector {
+    #[cfg(feature = "native-tls")]
+    NativeTls(native_tls::TlsConnector),
+    #[cfg(feature = "rustls")]
+    Rustls(Arc<rustls::ClientConfig>)
+}
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+impl Connector {
+    /// Perform the handshake for `host` over `stream`
+    pub(crate) fn connect(&self, host: &str, stream: TcpStream) -> Result<HttpStream, Error> {
+        match *self {
+            #[cfg(feature = "native-tls")]
+            Connector::NativeTls(ref connector) => Ok(HttpStream::TLS(connector.connect(host, stream)?)),
+            #[cfg(feature = "rustls")]
+            Connector::Rustls(ref config) => {
+                let name = ServerName::try_from(host).map_err(|_| TlsError::InvalidServerName)?.to_owned();
+                let conn = rustls::ClientConnection::new(config.clone(), name)?;
+                let mut stream = rustls::StreamOwned::new(conn, stream);
+                // rustls only shakes hands once data is sent, but errors
+                // and timings should be for the handshake
+                while stream.conn.is_handshaking() {
+                    stream.conn.complete_io(&mut stream.sock).map_err(rustls_handshake_error)?;
+                }
+                Ok(HttpStream::Rustls(Box::new(stream)))
+            }
+        }
+    }
+}
+
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+/// How many configs a [`Connectors`](struct.Connectors.html) remembers
+const MAX_CONNECTORS: usize = 8;
+
+/// Connectors built from each config in use, so they aren't rebuilt for
+/// every connection and can resume TLS sessions
+#[derive(Default)]
+pub(crate) struct Connectors {
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    cache: Mutex<Vec<(TlsConfig, Arc<Connector>)>>
+}
+impl Connectors {
+    pub(crate) const fn new() -> Connectors {
+        Connectors {
+            #[cfg(any(feature = "native-tls", feature = "rustls"))]
+            cache: Mutex::new(Vec::new())
+        }
+    }
+}
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+impl Connectors {
+    /// Get the connector for `config`, building it if it's new
+    fn get(&self, config: &TlsConfig) -> Result<Arc<Connector>, Error> {
+        let mut cache = self.cache.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(entry) = cache.iter().find(|entry| entry.0 == *config) {
+            return Ok(entry.1.clone());
+        }
+        let connector = Arc::new(config.connector()?);
+        if cache.len() == MAX_CONNECTORS {
+            cache.remove(0);
+        }
+        cache.push((config.clone(), connector.clone()));
+        Ok(connector)
+    }
+}
+
+/// The connectors for every request that isn't made by a
+/// [`Client`](../client/struct.Client.html), which keeps its own
+pub(crate) static SHARED: Connectors = Connectors::new();
+
+#[cfg(feature = "rustls")]
+/// rustls reports handshake failures as I/O errors
+fn rustls_handshake_error(error: io::Error) -> Error {
+    match error.get_ref().and_then(|inner| inner.downcast_ref::<rustls::Error>()) {
+        Some(inner) => Error::TlsHandshakeError(TlsError::Rustls(inner.clone())),
+        None => Error::IoError(error)
+    }
+}
+
+#[cfg(feature = "rustls")]
+/// Checks handshake signatures, but accepts any certificate. See
+/// [`danger_accept_invalid_certs`](struct.TlsConfig.html#method.danger_accept_invalid_certs)
+#[derive(Debug)]
+struct AcceptAll(Arc<rustls::crypto::CryptoProvider>);
+#[cfg(feature = "rustls")]
+impl ServerCertVerifier for AcceptAll {
+    fn verify_server_cert(&self, _: &CertificateDer, _: &[CertificateDer], _: &ServerName, _: &[u8], _: UnixTime)
+        -> Result<ServerCertVerified, rustls::Error>
+    {
+        Ok(ServerCertVerified::assertion())
+    }
+    fn verify_tls12_signature(&self, message: &[u8], cert: &CertificateDer, dss: &rustls::DigitallySignedStruct)
+        -> Result<HandshakeSignatureValid, rustls::Error>
+    {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+    fn verify_tls13_signature(&self, message: &[u8], cert: &CertificateDer, dss: &rustls::DigitallySignedStruct)
+        -> Result<HandshakeSignatureValid, rustls::Error>
+    {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(all(test, any(feature = "native-tls", feature = "rustls")))]
+pub mod test {
+    use super::*;
+    #[cfg(feature = "rustls")]
+    use std::io::{Read, Write};
+    #[cfg(feature = "rustls")]
+    use std::net::{SocketAddr, TcpListener};
+    #[cfg(feature = "rustls")]
+    use std::thread;
+    #[cfg(feature = "rustls")]
+    use std::time::{Duration, UNIX_EPOCH};
+    #[cfg(feature = "rustls")]
+    use x509::test::{unhex, CERTIFICATE};
+
+    #[cfg(feature = "native-tls")]
+    #[test]
+    fn native_tls() {
+        let config = TlsConfig::new().backend(Backend::NativeTls);
+        let invalid = config.clone().root_certificate(Certificate::Pem(b"not a certificate".to_vec()));
+        match invalid.connector() { Err(Error::TlsError(TlsError::NativeTls(_))) => (), _ => panic!() }
+
+        let invalid = config.clone().identity(Identity::Pkcs12 { der: Vec::new(), password: "secret".to_string() });
+        assert_eq!(format!("{:?}", invalid.identity), "Some(Identity::Pkcs12 { .. })");
+        match invalid.connector() { Err(Error::TlsError(TlsError::NativeTls(_))) => (), _ => panic!() }
+
+        #[cfg(not(feature = "rustls"))]
+        match config.clone().backend(Backend::Rustls).connector() { Err(Error::TlsError(TlsError::Unsupported(_))) => (), _ => panic!() }
+
+        assert!(config.built_in_roots(false).danger_accept_invalid_certs(true).connector().is_ok());
+    }
+    #[cfg(feature = "rustls")]
+    #[test]
+    fn rustls() {
+        let config = TlsConfig::new().backend(Backend::Rustls);
+        let invalid = config.clone().root_certificate(Certificate::Pem(b"not a certificate".to_vec()));
+        match invalid.connector() { Err(Error::TlsError(TlsError::Pem(_))) => (), _ => panic!() }
+
+        let invalid = config.clone().root_certificate(Certificate::Der(b"not a certificate".to_vec()));
+        match invalid.connector() { Err(Error::TlsError(TlsError::Rustls(_))) => (), _ => panic!() }
+
+        let invalid = config.clone().identity(Identity::Pkcs12 { der: Vec::new(), password: "secret".to_string() });
+        match invalid.connector() { Err(Error::TlsError(TlsError::Unsupported(_))) => (), _ => panic!() }
+
+        #[cfg(not(feature = "native-tls"))]
+        match config.clone().backend(Backend::NativeTls).connector() { Err(Error::TlsError(TlsError::Unsupported(_))) => (), _ => panic!() }
+
+        assert!(config.built_in_roots(false).danger_accept_invalid_certs(true).connector().is_ok());
+    }
+    #[cfg(feature = "rustls")]
+    /// Start a rustls server for `CERTIFICATE` that shakes hands with
+    /// `connections` clients, writes "x" and hangs up
+    pub fn server(connections: usize, alpn: &[&str]) -> SocketAddr {
+        // The PKCS #8 private key for `CERTIFICATE`
+        let key = unhex("\
+            308187020100301306072a8648ce3d020106082a8648ce3d030107046d306b02010104201292067ec027cb7ce204a31c\
+            02d0fb2b1a351b52dbe24dd65891fff6ea83c3e0a144034200040a565169c40d5e628b35c807fd05de78f36d325c0bfe\
+            0f3e6b9dca6b05a951e1a9a9b94cc5543f1c106da77fa04c759156720e16e5b94cc0081997e55b3feed2");
+        let mut server = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions().unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![CertificateDer::from(unhex(CERTIFICATE))], PrivateKeyDer::try_from(key).unwrap())
+            .unwrap();
+        server.alpn_protocols = alpn.iter().map(|protocol| protocol.as_bytes().to_vec()).collect();
+        let server = Arc::new(server);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming().take(connections) {
+                let conn = rustls::ServerConnection::new(server.clone()).unwrap();
+                let mut stream = rustls::StreamOwned::new(conn, stream.unwrap());
+                if stream.write_all(b"x").is_ok() {
+                    stream.conn.send_close_notify();
+                    let _ = stream.flush();
+                }
+            }
+        });
+        addr
+    }
+
+    #[cfg(feature = "rustls")]
+    #[test]
+    fn pinning() {
+        let addr = server(2, &[]);
+        let spki = unhex("8314e03a03b9db1e40525f05398704a50bb4c18ea3e0ba0b6ab35c7e0e76aa30");
+        let mut pin = [0; 32];
+        pin.copy_from_slice(&spki);
+        let config = TlsConfig::new().backend(Backend::Rustls).danger_accept_invalid_certs(true).pin_sha256([0; 32]);
+
+        let stream = config.clone().pin_sha256(pin).connect(&Connectors::default(), "localhost", TcpStream::connect(addr).unwrap()).unwrap();
+        let info = stream.tls_info().unwrap().unwrap();
+        assert_eq!(info.peer_certificates.len(), 1);
+        let cert = &info.peer_certificates[0];
+        assert_eq!(cert.der, unhex(CERTIFICATE));
+        assert_eq!(cert.subject, "C=SE, O=Example, CN=minttp test");
+        assert_eq!(&cert.fingerprint[..], &unhex("f422a1a212326f9b0a1d4940579c42039958fd61f72d73e25120f3bdaac7f7ca")[..]);
+        assert_eq!(cert.spki_sha256, pin);
+        assert!(cert.is_valid_at(UNIX_EPOCH + Duration::from_secs(2000000000)));
+        assert!(!cert.is_valid_at(UNIX_EPOCH));
+
+        match config.connect(&Connectors::default(), "localhost", TcpStream::connect(addr).unwrap()) { Err(Error::PinMismatch) => (), _ => panic!() }
+        match PeerCertificate::from_der(b"not a certificate".to_vec()) {
+            Err(Error::TlsError(TlsError::InvalidCertificate)) => (),
+            _ => panic!()
+        }
+    }
+    #[cfg(feature = "rustls")]
+    #[test]
+    fn resumption() {
+        let addr = server(3, &["h2", "http/1.1"]);
+        let config = TlsConfig::new().backend(Backend::Rustls).danger_accept_invalid_certs(true);
+        let connectors = Connectors::default();
+        let connect = |config: &TlsConfig| {
+            let mut stream = config.connect(&connectors, "localhost", TcpStream::connect(addr).unwrap()).unwrap();
+            // Session tickets arrive after the handshake
+            stream.read_to_end(&mut Vec::new()).unwrap();
+            stream.tls_info().unwrap().unwrap()
+        };
+
+        let info = connect(&config);
+        assert_eq!((info.alpn_protocol.as_deref(), info.resumed), (Some("http/1.1"), false));
+        let info = connect(&config);
+        assert_eq!((info.alpn_protocol.as_deref(), info.resumed), (Some("http/1.1"), true));
+
+        let info = connect(&config.alpn_protocols(&["h2", "http/1.1"]));
+        assert_eq!((info.alpn_protocol.as_deref(), info.resumed), (Some("h2"), false));
+    }
+    #[cfg(feature = "rustls")]
+    #[test]
+    fn alpn() {
+        let addr = server(2, &["h2"]);
+        let tls = TlsConfig::new().backend(Backend::Rustls).danger_accept_invalid_certs(true).alpn_protocols(&["h2"]);
+
+        let url = format!("https://localhost:{}/", addr.port()).parse().unwrap();
+        match ::Request::new(url).tls(tls.clone()).request() { Err(Error::UnsupportedProtocol) => (), _ => panic!() }
+
+        // Nothing is sent, so the caller can speak h2 themselves
+        let headers = ::std::collections::HashMap::new();
+        let diy = ::DIYRequest {
+            ssl: true,
+            host: "localhost",
+            port: addr.port(),
+            method: "GET",
+            path: "/",
+            http_version: "1.1",
+            headers: &headers,
+            body: None
+        };
+        let mut stream = ::diy_request_tls(&diy, &tls, &mut ::Timings::default()).unwrap();
+        assert_eq!(stream.alpn_protocol().unwrap().as_deref(), Some("h2"));
+        let mut data = Vec::new();
+        stream.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"x");
+    }
+}
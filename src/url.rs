@@ -6,6 +6,8 @@ use std::str::FromStr;
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Url {
     pub protocol: String,
+    /// The host name or IP address. IPv6 addresses are stored without
+    /// brackets.
     pub host: String,
     pub port: u16,
     pub path: String,
@@ -27,6 +29,30 @@ pub fn encode(input: &str) -> String {
 
     output
 }
+/// The port used when a URL doesn't specify one
+pub fn default_port(protocol: &str) -> u16 {
+    if protocol == "https" { 443 } else { 80 }
+}
+
+impl Url {
+    /// The host, with brackets if it's an IPv6 address
+    pub fn host_bracketed(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        }
+    }
+    /// The value of the "Host" header for this URL: the host, and the port
+    /// if it isn't the default one for the protocol
+    pub fn host_header(&self) -> String {
+        if self.port == default_port(&self.protocol) {
+            self.host_bracketed()
+        } else {
+            format!("{}:{}", self.host_bracketed(), self.port)
+        }
+    }
+}
 impl FromStr for Url {
     type Err = Box<::std::error::Error>;
 
@@ -34,18 +60,33 @@ impl FromStr for Url {
         let mut parts: Vec<_> = s.splitn(2, "://").collect();
         let protocol = if parts.len() == 2 { parts[0] } else { "http" };
 
-        parts = parts[parts.len() - 1].splitn(2, ':').collect();
-        let mut host = parts[0];
+        let rest = parts[parts.len() - 1];
+        let authority_end = rest.find(|c| c == '/' || c == '?').unwrap_or(rest.len());
+        let (authority, rest) = rest.split_at(authority_end);
 
-        let port = if let Some(port) = parts.get(1) {
-            port.splitn(2, '/').next().unwrap().parse()?
+        let (host, port) = if authority.starts_with('[') {
+            match authority.find(']') {
+                Some(end) => (&authority[1..end], &authority[end + 1..]),
+                None => return Err("unclosed bracket in URL host".into())
+            }
         } else {
-            host = host.splitn(2, '/').next().unwrap();
-
-            if protocol == "https" { 443 } else { 80 }
+            match authority.rfind(':') {
+                Some(colon) => (&authority[..colon], &authority[colon..]),
+                None => (authority, "")
+            }
+        };
+        let port = match port {
+            "" => default_port(protocol),
+            _ if port.starts_with(':') => port[1..].parse()?,
+            _ => return Err("unexpected characters after URL host".into())
         };
 
-        parts = parts[parts.len() - 1].splitn(2, '/').collect();
+        parts = if rest.starts_with('?') {
+            // "example.com?query" has an empty path
+            vec!["", rest]
+        } else {
+            rest.splitn(2, '/').collect()
+        };
 
         let mut fullpath = '/'.to_string();
         let mut path = '/'.to_string();
@@ -74,7 +115,7 @@ impl fmt::Display for Url {
             f,
             "{}://{}:{}{}",
             self.protocol,
-            self.host,
+            self.host_bracketed(),
             self.port,
             self.fullpath
         )
@@ -103,6 +144,24 @@ mod test {
         );
     }
     #[test]
+    fn hosts() {
+        let url = "http://[::1]:8080/a:b?c".parse::<Url>().unwrap();
+        assert_eq!((&*url.host, url.port, &*url.fullpath), ("::1", 8080, "/a:b?c"));
+        assert_eq!(url.host_header(), "[::1]:8080");
+        assert_eq!(url.to_string(), "http://[::1]:8080/a:b?c");
+
+        assert_eq!("https://[::1]/".parse::<Url>().unwrap().host_header(), "[::1]");
+        assert_eq!("example.com:8080".parse::<Url>().unwrap().host_header(), "example.com:8080");
+        assert_eq!("example.com:80/x".parse::<Url>().unwrap().host_header(), "example.com");
+        assert_eq!("https://example.com:80/".parse::<Url>().unwrap().host_header(), "example.com:80");
+
+        let url = "example.com?q=1".parse::<Url>().unwrap();
+        assert_eq!((&*url.path, &*url.fullpath, url.query), ("/", "/?q=1", Some("q=1".to_string())));
+
+        assert!("[::1/".parse::<Url>().is_err());
+        assert!("[::1]x/".parse::<Url>().is_err());
+    }
+    #[test]
     fn test2() {
         let url = "example.com/path".parse::<Url>().unwrap();
         assert_eq!(
@@ -0,0 +1,238 @@
+use date::days_from_civil;
+use std::fmt::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const INTEGER: u8 = 0x02;
+const OID: u8 = 0x06;
+const VERSION: u8 = 0xA0;
+
+/// The parts of a certificate minttp reports
+pub struct Certificate<'a> {
+    pub subject: String,
+    pub issuer: String,
+    pub not_before: SystemTime,
+    pub not_after: SystemTime,
+    /// The whole DER encoded SubjectPublicKeyInfo
+    pub spki: &'a [u8]
+}
+
+/// Reads DER elements one after another
+struct Reader<'a> {
+    data: &'a [u8]
+}
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Reader<'a> { Reader { data: data } }
+
+    /// Read the next element as (tag, contents, whole element)
+    fn next(&mut self) -> Option<(u8, &'a [u8], &'a [u8])> {
+        let tag = *self.data.first()?;
+        if tag & 0x1F == 0x1F {
+            // Multi-byte tags aren't used by certificates
+            return None;
+        }
+        let first = *self.data.get(1)? as usize;
+        let (len, header) = if first < 0x80 {
+            (first, 2)
+        } else {
+            let count = first & 0x7F;
+            if count == 0 || count > 4 {
+                return None;
+            }
+            let bytes = self.data.get(2..2 + count)?;
+            (bytes.iter().fold(0, |len, &b| len << 8 | b as usize), 2 + count)
+        };
+        let whole = self.data.get(..header.checked_add(len)?)?;
+        self.data = &self.data[whole.len()..];
+        Some((tag, &whole[header..], whole))
+    }
+    /// Read the contents of the next element, if it has the tag `tag`
+    fn expect(&mut self, tag: u8) -> Option<&'a [u8]> {
+        match self.next()? {
+            (actual, contents, _) if actual == tag => Some(contents),
+            _ => None
+        }
+    }
+}
+
+/// Parse a DER encoded X.509 certificate
+pub fn parse<'a>(der: &'a [u8]) -> Option<Certificate<'a>> {
+    let mut cert = Reader::new(Reader::new(der).expect(SEQUENCE)?);
+    let mut tbs = Reader::new(cert.expect(SEQUENCE)?);
+
+    let mut serial = tbs.next()?;
+    if serial.0 == VERSION {
+        serial = tbs.next()?;
+    }
+    if serial.0 != INTEGER {
+        return None;
+    }
+    tbs.expect(SEQUENCE)?; // signature algorithm
+    let issuer = name(tbs.expect(SEQUENCE)?)?;
+    let mut validity = Reader::new(tbs.expect(SEQUENCE)?);
+    let not_before = time(validity.next()?)?;
+    let not_after = time(validity.next()?)?;
+    let subject = name(tbs.expect(SEQUENCE)?)?;
+    let spki = match tbs.next()? {
+        (SEQUENCE, _, whole) => whole,
+        _ => return None
+    };
+
+    Some(Certificate {
+        subject: subject,
+        issuer: issuer,
+        not_before: not_before,
+        not_after: not_after,
+        spki: spki
+    })
+}
+
+/// Format a distinguished name in the order it's encoded, such as
+/// `C=SE, O=Example, CN=example.com`
+fn name(der: &[u8]) -> Option<String> {
+    let mut rdns = Reader::new(der);
+    let mut output = String::new();
+    while !rdns.data.is_empty() {
+        let mut attributes = Reader::new(rdns.expect(SET)?);
+        let mut first = true;
+        while !attributes.data.is_empty() {
+            let mut attribute = Reader::new(attributes.expect(SEQUENCE)?);
+            let oid = oid(attribute.expect(OID)?)?;
+            let (tag, value, whole) = attribute.next()?;
+
+            output.push_str(if first && !output.is_empty() { ", " } else if first { "" } else { "+" });
+            first = false;
+            output.push_str(match &*oid {
+                "2.5.4.3" => "CN",
+                "2.5.4.5" => "serialNumber",
+                "2.5.4.6" => "C",
+                "2.5.4.7" => "L",
+                "2.5.4.8" => "ST",
+                "2.5.4.9" => "street",
+                "2.5.4.10" => "O",
+                "2.5.4.11" => "OU",
+                "0.9.2342.19200300.100.1.25" => "DC",
+                "1.2.840.113549.1.9.1" => "emailAddress",
+                oid => oid
+            });
+            output.push('=');
+            match tag {
+                // UTF8String, PrintableString, IA5String
+                0x0C | 0x13 | 0x16 => output.push_str(&String::from_utf8_lossy(value)),
+                // TeletexString, which is Latin-1 in practice
+                0x14 => output.extend(value.iter().map(|&b| b as char)),
+                // BMPString
+                0x1E => {
+                    let units: Vec<u16> = value.chunks(2).map(|c| (c[0] as u16) << 8 | *c.get(1).unwrap_or(&0) as u16).collect();
+                    output.push_str(&String::from_utf16_lossy(&units));
+                },
+                // Anything else as hex, like RFC 4514 does
+                _ => {
+                    output.push('#');
+                    for byte in whole {
+                        write!(output, "{:02x}", byte).unwrap();
+                    }
+                }
+            }
+        }
+    }
+    Some(output)
+}
+
+/// Format an object identifier as dotted decimal
+fn oid(der: &[u8]) -> Option<String> {
+    let mut arcs = Vec::new();
+    let mut value: u64 = 0;
+    for &byte in der {
+        value = value.checked_mul(128)? | (byte & 0x7F) as u64;
+        if byte & 0x80 == 0 {
+            arcs.push(value);
+            value = 0;
+        }
+    }
+    if arcs.is_empty() || der.last()? & 0x80 != 0 {
+        return None;
+    }
+
+    // The first two arcs share a subidentifier. Only the last one, 2, can
+    // have a second arc of 40 or more.
+    let first = arcs[0];
+    let mut output = if first < 80 {
+        format!("{}.{}", first / 40, first % 40)
+    } else {
+        format!("2.{}", first - 80)
+    };
+    for arc in &arcs[1..] {
+        write!(output, ".{}", arc).unwrap();
+    }
+    Some(output)
+}
+
+/// Parse a UTCTime or GeneralizedTime in UTC, like `261019021750Z`
+fn time((tag, value, _): (u8, &[u8], &[u8])) -> Option<SystemTime> {
+    let value = ::std::str::from_utf8(value).ok()?;
+    let (year, rest) = match tag {
+        0x17 => {
+            let year: i64 = value.get(..2)?.parse().ok()?;
+            (if year >= 50 { 1900 + year } else { 2000 + year }, value.get(2..)?)
+        },
+        0x18 => (value.get(..4)?.parse().ok()?, value.get(4..)?),
+        _ => return None
+    };
+    if rest.len() != 11 || !rest.ends_with('Z') || !rest[..10].bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let field = |i: usize| rest[i..i + 2].parse::<u32>().unwrap();
+    let days = days_from_civil(year, field(0), field(2));
+    let secs = days * 86400 + (field(4) * 3600 + field(6) * 60 + field(8)) as i64;
+
+    Some(if secs >= 0 {
+        UNIX_EPOCH + Duration::from_secs(secs as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(-secs as u64)
+    })
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    /// A self-signed EC certificate for "C=SE, O=Example, CN=minttp test"
+    pub const CERTIFICATE: &str = "\
+        308201ac30820152a003020102020107300a06082a8648ce3d0403023035310b30090603550406130253453110300e06\
+        0355040a0c074578616d706c653114301206035504030c0b6d696e7474702074657374301e170d32363130313930323137\
+        35305a170d3336313031363032313735305a3035310b30090603550406130253453110300e060355040a0c074578616d70\
+        6c653114301206035504030c0b6d696e74747020746573743059301306072a8648ce3d020106082a8648ce3d0301070342\
+        00040a565169c40d5e628b35c807fd05de78f36d325c0bfe0f3e6b9dca6b05a951e1a9a9b94cc5543f1c106da77fa04c75\
+        9156720e16e5b94cc0081997e55b3feed2a3533051301d0603551d0e041604145c019ce67ff1670624c2a1753bff99518f\
+        930482301f0603551d230418301680145c019ce67ff1670624c2a1753bff99518f930482300f0603551d130101ff040530\
+        030101ff300a06082a8648ce3d04030203480030450220333dc70b680813b312056d9384d036972d7ee6e5bf3f83f35f30\
+        61a1fac3d5a6022100e8e5eee8fa905623eb37f6261ec1dcd31c057fc0beb396eb2757fc6369c1c79d";
+    pub fn unhex(hex: &str) -> Vec<u8> {
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn parse_certificate() {
+        let der = unhex(CERTIFICATE);
+        let cert = parse(&der).unwrap();
+        assert_eq!(cert.subject, "C=SE, O=Example, CN=minttp test");
+        assert_eq!(cert.issuer, cert.subject);
+        assert_eq!(cert.not_before, UNIX_EPOCH + Duration::from_secs(1792376270));
+        assert_eq!(cert.not_after, UNIX_EPOCH + Duration::from_secs(2107736270));
+        assert_eq!(cert.spki.len(), 91);
+
+        assert!(parse(&der[..100]).is_none());
+        assert!(parse(b"").is_none());
+
+        assert_eq!(oid(&[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x09, 0x01]).unwrap(), "1.2.840.113549.1.9.1");
+        assert_eq!(oid(&[0x55, 0x04, 0x03]).unwrap(), "2.5.4.3");
+        assert_eq!(oid(&[0x78]).unwrap(), "2.40");
+        assert_eq!(oid(&[0x88, 0x37, 0x03]).unwrap(), "2.999.3");
+        assert!(oid(&[0x2A, 0x86]).is_none());
+        assert_eq!(time((0x17, b"491231235959Z", b"")).unwrap(), UNIX_EPOCH + Duration::from_secs(2524607999));
+        assert_eq!(time((0x18, b"19700101000000Z", b"")).unwrap(), UNIX_EPOCH);
+        assert!(time((0x17, b"4912312359Z", b"")).is_none());
+    }
+}